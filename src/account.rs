@@ -0,0 +1,267 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+use anyhow::{anyhow, bail, Context, Result};
+use jiff::Timestamp;
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use ureq::Agent;
+
+use crate::debug::debug;
+use crate::error::CbcError;
+use crate::exit::{Code, Failure};
+
+/// CBC's sign-in service, which takes an email and password directly (OAuth's resource owner
+/// password flow) as CBC Gem's apps do.
+const TOKEN_URL: &str = "https://login.cbc.radio-canada.ca/bef1b538-1950-4283-9b27-b096cbc18070/B2C_1A_gem_cbc_ropc/oauth2/v2.0/token";
+const CLIENT_ID: &str = "fc05b0ee-3865-4400-a3cc-3da82c330c23";
+const SCOPES: &str = "openid offline_access \
+    https://rcmnb2cprod.onmicrosoft.com/84593b65-0ef6-4a72-891c-d351ddd50aab/media-drmt \
+    https://rcmnb2cprod.onmicrosoft.com/84593b65-0ef6-4a72-891c-d351ddd50aab/media-meta \
+    https://rcmnb2cprod.onmicrosoft.com/84593b65-0ef6-4a72-891c-d351ddd50aab/media-validation.read";
+/// Turns an access token into the claims token medianet wants for member-only streams.
+const PROFILE_URL: &str =
+    "https://services.radio-canada.ca/ott/subscription/v2/gem/Subscriber/profile?device=web";
+/// Refresh the access token when it has less than this many seconds left.
+const EXPIRY_MARGIN: i64 = 60;
+
+/// The claims token for this run, once fetched.
+static CLAIMS: OnceCell<Option<String>> = OnceCell::new();
+
+/// What's saved after signing in. The password itself is never stored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Session {
+    email: String,
+    access_token: String,
+    refresh_token: Option<String>,
+    /// Seconds since the Unix epoch
+    expires_at: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    refresh_token: Option<String>,
+    expires_in: i64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Profile {
+    claims_token: String,
+}
+
+fn path() -> Result<PathBuf> {
+    let dir = dirs::config_dir().ok_or_else(|| anyhow!("couldn't find config directory"))?;
+    Ok(dir.join("cbc-sl").join("account.json"))
+}
+
+/// Sign in as `email`, prompting for the password unless `$CBC_SL_PASSWORD` is set, and save
+/// the tokens for later runs.
+pub(crate) fn login(agent: &Agent, email: Option<&str>) -> Result<()> {
+    let email = match email {
+        Some(email) => email.to_owned(),
+        None => prompt("Email: ", false)?,
+    };
+    let password = match std::env::var("CBC_SL_PASSWORD") {
+        Ok(password) => password,
+        Err(_) => prompt("Password: ", true)?,
+    };
+    ensure_filled(&email, "email")?;
+    ensure_filled(&password, "password")?;
+    let session = request_tokens(
+        agent,
+        &email,
+        &[("grant_type", "password"), ("username", &email), ("password", &password)],
+    )?;
+    save(&session)?;
+    println!("Signed in as {email}.");
+    Ok(())
+}
+
+/// Forget the saved sign-in.
+pub(crate) fn logout() -> Result<()> {
+    let path = path()?;
+    match fs::remove_file(&path) {
+        Ok(()) => println!("Signed out."),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => println!("Not signed in."),
+        Err(e) => return Err(e).with_context(|| format!("removing {}", path.display())),
+    }
+    Ok(())
+}
+
+/// The claims token to send medianet for member-only streams, or `None` if not signed in. The
+/// access token is refreshed first if it has expired.
+pub(crate) fn claims_token(agent: &Agent) -> Result<Option<&'static str>> {
+    let claims = CLAIMS.get_or_try_init(|| {
+        let Some(mut session) = load()? else { return Ok::<_, anyhow::Error>(None) };
+        if session.expires_at - EXPIRY_MARGIN <= Timestamp::now().as_second() {
+            session = refresh(agent, &session)?;
+        }
+        debug!("request method=GET url={PROFILE_URL}");
+        let profile: Profile = agent
+            .get(PROFILE_URL)
+            .set("Authorization", &format!("Bearer {}", session.access_token))
+            .call()
+            .map_err(CbcError::from)?
+            .into_json()
+            .map_err(|e| CbcError::parse("the account profile", e))?;
+        Ok(Some(profile.claims_token))
+    })?;
+    Ok(claims.as_deref())
+}
+
+fn refresh(agent: &Agent, session: &Session) -> Result<Session> {
+    let Some(refresh_token) = &session.refresh_token else {
+        bail!(expired());
+    };
+    debug!("account refreshing");
+    let session = request_tokens(
+        agent,
+        &session.email,
+        &[("grant_type", "refresh_token"), ("refresh_token", refresh_token)],
+    )
+    .map_err(|e| e.context(expired()))?;
+    save(&session)?;
+    Ok(session)
+}
+
+fn expired() -> Failure {
+    Failure::new(Code::InvalidInput, "the CBC sign-in has expired; run `cbc-sl login` again")
+}
+
+/// Ask the sign-in service for tokens with `grant`, the grant type and its credentials.
+fn request_tokens(agent: &Agent, email: &str, grant: &[(&str, &str)]) -> Result<Session> {
+    let mut form = vec![("client_id", CLIENT_ID), ("scope", SCOPES)];
+    form.extend_from_slice(grant);
+    debug!("request method=POST url={TOKEN_URL}");
+    let tokens: TokenResponse = match agent.post(TOKEN_URL).send_form(&form) {
+        Ok(resp) => resp.into_json().map_err(|e| CbcError::parse("the sign-in response", e))?,
+        // a wrong password comes back as a 400 with an OAuth error
+        Err(ureq::Error::Status(400 | 401, resp)) => {
+            let body = resp.into_string().unwrap_or_default();
+            debug!("login_rejected body={body}");
+            bail!(Failure::new(Code::InvalidInput, "CBC rejected the email or password"));
+        }
+        Err(e) => return Err(CbcError::from(e).into()),
+    };
+    Ok(Session {
+        email: email.to_owned(),
+        access_token: tokens.access_token,
+        refresh_token: tokens.refresh_token,
+        expires_at: Timestamp::now().as_second() + tokens.expires_in,
+    })
+}
+
+fn load() -> Result<Option<Session>> {
+    let path = path()?;
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+    };
+    let session = serde_json::from_str(&contents)
+        .with_context(|| format!("reading {}; run `cbc-sl login` again", path.display()))?;
+    Ok(Some(session))
+}
+
+/// Write the session where only this user can read it.
+fn save(session: &Session) -> Result<()> {
+    let path = path()?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // the mode only applies to new files
+        if path.exists() {
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+        }
+    }
+    let mut file = options.open(&path).with_context(|| format!("writing {}", path.display()))?;
+    file.write_all(serde_json::to_string(session)?.as_bytes())?;
+    Ok(())
+}
+
+fn ensure_filled(value: &str, what: &str) -> Result<()> {
+    if value.is_empty() {
+        bail!(Failure::new(Code::InvalidInput, format!("no {what} given")));
+    }
+    Ok(())
+}
+
+/// Read a line from the terminal after printing `label`, without echoing it if `hidden`.
+fn prompt(label: &str, hidden: bool) -> Result<String> {
+    eprint!("{label}");
+    io::stderr().flush()?;
+    let echo = if hidden { Echo::off() } else { None };
+    let mut line = String::new();
+    let read = io::stdin().lock().read_line(&mut line);
+    if echo.is_some() {
+        drop(echo);
+        eprintln!();
+    }
+    read?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_owned())
+}
+
+/// Turns terminal echo back on when dropped.
+struct Echo {
+    #[cfg(unix)]
+    previous: libc::termios,
+    #[cfg(windows)]
+    previous: windows::Win32::System::Console::CONSOLE_MODE,
+}
+
+impl Echo {
+    /// Stop the terminal echoing what's typed, or `None` if stdin isn't a terminal.
+    #[cfg(unix)]
+    fn off() -> Option<Self> {
+        unsafe {
+            let mut previous: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut previous) != 0 {
+                return None;
+            }
+            let mut quiet = previous;
+            quiet.c_lflag &= !libc::ECHO;
+            (libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &quiet) == 0)
+                .then_some(Self { previous })
+        }
+    }
+
+    #[cfg(windows)]
+    fn off() -> Option<Self> {
+        use windows::Win32::System::Console::{
+            GetConsoleMode, GetStdHandle, SetConsoleMode, CONSOLE_MODE, ENABLE_ECHO_INPUT,
+            STD_INPUT_HANDLE,
+        };
+        unsafe {
+            let input = GetStdHandle(STD_INPUT_HANDLE).ok()?;
+            let mut previous = CONSOLE_MODE::default();
+            GetConsoleMode(input, &mut previous).ok()?;
+            SetConsoleMode(input, previous & !ENABLE_ECHO_INPUT).ok()?;
+            Some(Self { previous })
+        }
+    }
+}
+
+impl Drop for Echo {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.previous);
+        }
+        #[cfg(windows)]
+        unsafe {
+            use windows::Win32::System::Console::{GetStdHandle, SetConsoleMode, STD_INPUT_HANDLE};
+            if let Ok(input) = GetStdHandle(STD_INPUT_HANDLE) {
+                let _ = SetConsoleMode(input, self.previous);
+            }
+        }
+    }
+}
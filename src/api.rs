@@ -1,142 +1,558 @@
-use anyhow::Result;
+//! Types for what CBC.ca sends, and for what cbc-sl prints about it.
+//!
+//! Two kinds of thing live here. The deserialized types ([GqlResponse] and [Node] from the
+//! GraphQL listings, [InitialState] from player pages, [Stream] from `medianet` URLs) mirror
+//! CBC's API and may break whenever CBC changes it, in a minor release if need be. What cbc-sl
+//! writes out ([Node::to_json], [ClipMetadata]) is stable: fields are added, never renamed or
+//! removed.
+//!
+//! ```
+//! use cbc_sl::api::format_duration;
+//!
+//! assert_eq!(format_duration(5400.0), "1h30m");
+//! assert_eq!(format_duration(0.0), "--");
+//! ```
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use clap::ValueEnum;
 use jiff::{tz::TimeZone, Span, Timestamp, Zoned};
+use lazy_regex::regex;
 use owo_colors::{OwoColorize, Stream::Stdout};
-use serde::Deserialize;
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
+use crate::error::CbcError;
+use crate::log::debug;
+
+/// A response from `https://www.cbc.ca/graphql` to an `allContentItems` query, as used for
+/// the listings. Like everything read from CBC here, this mirrors CBC's API and may break
+/// whenever CBC changes it.
 #[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct GqlResponse {
-    pub(crate) data: Data,
+    pub data: Data,
     // pub(crate) extensions: Extensions,
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Data {
-    pub(crate) all_content_items: AllContentItems,
+    pub all_content_items: AllContentItems,
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(from = "RawContentItems")]
 pub struct AllContentItems {
-    pub(crate) nodes: Vec<Node>,
+    /// The videos, in the order CBC sent them. Entries that can't be read are left out.
+    pub nodes: Vec<Node>,
+    /// How many entries CBC sent, counting any that were left out. CBC doesn't say how many
+    /// there are in total, but if this is the whole page size asked for, there are probably more.
+    pub received: usize,
+    /// The IDs (as CBC sent them, or `unknown`) of entries that were left out because they
+    /// couldn't be read, for telling the user
+    pub skipped: Vec<String>,
 }
 
-#[derive(Debug, Clone, PartialEq, Deserialize)]
-#[serde(rename_all = "camelCase")]
+impl AllContentItems {
+    /// Whether CBC probably has more than it sent, going by whether the page came back full.
+    pub fn has_more(&self, page_size: usize) -> bool {
+        self.received >= page_size
+    }
+}
+
+#[derive(Deserialize)]
+struct RawContentItems {
+    nodes: Vec<serde_json::Value>,
+}
+
+impl From<RawContentItems> for AllContentItems {
+    fn from(raw: RawContentItems) -> Self {
+        let received = raw.nodes.len();
+        let (nodes, skipped) = skip_malformed(raw.nodes);
+        Self { nodes, received, skipped }
+    }
+}
+
+/// Deserializes the listing one node at a time, dropping any that aren't a usable video, e.g.
+/// missing `media` or with an unreadable date, so one odd entry from CBC doesn't break the whole
+/// listing. Returns the nodes and the IDs of those dropped.
+fn skip_malformed(values: Vec<serde_json::Value>) -> (Vec<Node>, Vec<String>) {
+    let mut nodes = Vec::with_capacity(values.len());
+    let mut skipped = Vec::new();
+    for value in values {
+        let problem = match Node::deserialize(&value) {
+            Ok(node) => match node.timestamp() {
+                Ok(_) => {
+                    nodes.push(node);
+                    continue;
+                }
+                Err(e) => format!("bad publishedAt: {e}"),
+            },
+            Err(e) => e.to_string(),
+        };
+        let id = value.get("id").map_or("unknown".to_owned(), ToString::to_string);
+        debug!("skipped_node id={id} problem={problem}");
+        skipped.push(id);
+    }
+    (nodes, skipped)
+}
+
+/// A listing entry: one video, live or on demand. It's read from CBC's camelCase GraphQL, so
+/// which fields exist mirrors CBC's API and may change. It's written out as snake_case (see
+/// [Node::to_json]), and those field names are a stable contract that scripts rely on: fields
+/// may be added, but not renamed or removed.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all(deserialize = "camelCase", serialize = "snake_case"))]
 pub struct Node {
-    pub(crate) id: i64,
-    pub(crate) url: String,
-    pub(crate) title: String,
-    // pub(crate) section_list: Vec<Option<serde_json::Value>>,
+    /// CBC's internal ID for the listing entry. Not the ID in player URLs; see
+    /// [Node::proper_id]
+    pub id: i64,
+    /// The player page, ending in the video's ID
+    pub url: String,
+    pub title: String,
+    /// Where CBC files it on the site, e.g. `Sports/Olympics`
+    #[serde(default, deserialize_with = "lenient")]
+    pub section_list: Vec<String>,
     // pub(crate) section_labels: Vec<Option<serde_json::Value>>,
     // pub(crate) related_links: Vec<Option<serde_json::Value>>,
     // pub(crate) deck: Option<serde_json::Value>,
-    // pub(crate) description: String,
-    pub(crate) flag: Flag,
+    #[serde(default, deserialize_with = "lenient")]
+    pub description: Option<String>,
+    /// Whether this is a live event (past, present or future) or an ordinary video
+    pub flag: Flag,
     // pub(crate) image_large: String,
-    // pub(crate) image: HashMap<String, Image>,
+    /// Thumbnails by CBC's size name, e.g. `_16x9_940`
+    #[serde(default, deserialize_with = "lenient")]
+    pub image: HashMap<String, Option<Image>>,
     // pub(crate) source: String,
-    // pub(crate) source_id: String,
-    pub(crate) published_at: String,
-    pub(crate) updated_at: String,
+    /// The video's ID, as in player URLs
+    #[serde(default, deserialize_with = "lenient")]
+    pub source_id: Option<String>,
+    /// Milliseconds since the Unix epoch. For live events, this is when they start
+    pub published_at: String,
+    /// Milliseconds since the Unix epoch
+    pub updated_at: String,
     // pub(crate) sponsor: Option<serde_json::Value>,
     #[serde(rename = "type")]
     // pub(crate) node_type: Type,
-    pub(crate) node_type: String,
-    // pub(crate) show_name: Option<String>,
+    /// Always `video`, given how the listings are queried
+    pub node_type: String,
+    #[serde(default, deserialize_with = "lenient")]
+    pub show_name: Option<String>,
     // pub(crate) authors: Vec<Option<serde_json::Value>>,
     // pub(crate) comments_enabled: bool,
     // pub(crate) contextual_headlines: Vec<Option<serde_json::Value>>,
     // pub(crate) media_id: Option<String>,
-    pub(crate) media: Media,
+    pub media: Media,
     // pub(crate) headline_data: Option<serde_json::Value>,
     // pub(crate) components: Option<serde_json::Value>,
-    // pub(crate) categories: Vec<Category>,
+    /// The categories CBC tags it with, like `summer-olympics-live`
+    #[serde(default, deserialize_with = "lenient")]
+    pub categories: Vec<Category>,
 }
 
+/// Fields left out of JSON output unless `--full` is given, since they can be long.
+const FULL_ONLY_FIELDS: &[&str] = &["description", "categories", "section_list"];
+
 impl Node {
-    pub(crate) fn proper_id(&self) -> &str {
-        self.url.split('/').last().unwrap()
+    /// Whether this is an event for `sport` (in lowercase): one of its categories or its title
+    /// mentions it.
+    pub fn is_sport(&self, sport: &str) -> bool {
+        let mentions = |text: &str| text.to_lowercase().contains(sport);
+        mentions(&self.title)
+            || self.categories.iter().any(|category| {
+                category.name.as_deref().is_some_and(mentions)
+                    || category.slug.as_deref().is_some_and(mentions)
+            })
+    }
+
+    /// Whether CBC filed this under `section` (e.g. "Sports"), itself or any part of it.
+    pub fn in_section(&self, section: &str) -> bool {
+        self.section_list.iter().any(|s| {
+            s.split('/').next().is_some_and(|top| top.trim().eq_ignore_ascii_case(section))
+        })
+    }
+
+    /// Convert to JSON for machine-readable output, dropping the bulkier fields unless `full`.
+    /// The media gets a `duration_text` alongside the raw seconds.
+    pub fn to_json(&self, full: bool) -> serde_json::Result<serde_json::Value> {
+        let mut value = serde_json::to_value(self)?;
+        if let Some(media) = value.get_mut("media").and_then(|m| m.as_object_mut()) {
+            let text = format_duration(self.media.duration);
+            media.insert("duration_text".to_owned(), serde_json::Value::String(text));
+        }
+        if !full {
+            if let Some(map) = value.as_object_mut() {
+                for field in FULL_ONLY_FIELDS {
+                    map.remove(*field);
+                }
+            }
+        }
+        Ok(value)
     }
 
-    pub(crate) fn to_human(&self, full_urls: bool) -> Result<String> {
+    pub fn proper_id(&self) -> &str {
+        self.url.split('/').next_back().unwrap()
+    }
+
+    /// Formats the node for listings by filling in `template`. Tokens are `{id}`, `{url}`,
+    /// `{title}`, `{date}`, `{status}`, `{duration}`, `{note}`, which is the status and
+    /// date together as shown by default, `{cc}`, which is ` [CC]` if there are captions, and
+    /// `{show}`, which is ` — ` and the show name if there is one. Unknown tokens are left
+    /// as-is. If the line would be wider than `max_width` columns, the title is shortened to fit.
+    pub fn to_human(
+        &self,
+        template: &str,
+        time_format: TimeFormat,
+        max_width: Option<usize>,
+    ) -> Result<String, CbcError> {
         let now = Zoned::now();
         let date = self.date()?;
-        let same_day = now.date() == date.date();
-        let fmt = if same_day { "%H:%M" } else { "%b %d %H:%M" };
-        let date_time = self.date()?.strftime(fmt);
-
-        // live or upcoming
-        let lu = matches!(self.flag, Flag::Live);
-        let note = if lu {
-            match self.is_live()? {
-                true => format!(
-                    "({} @ {}) ",
-                    "STARTED ".if_supports_color(Stdout, |text| text.bright_white().on_black()),
+        let date_time = date.strftime(time_format.pattern(now.date() == date.date())).to_string();
+
+        let status = if matches!(self.flag, Flag::Live) { Some(self.status()?) } else { None };
+        let note = if let Some(status) = status {
+            let since_start = now.timestamp().as_second() - self.timestamp()?.as_second();
+            match status {
+                LiveStatus::Live => format!(
+                    "({} {} ago @ {})",
+                    "STARTED".if_supports_color(Stdout, |text| text.bright_white().on_black()),
+                    compact_duration(since_start),
                     date_time
                 ),
-                false => format!(
-                    "({} @ {}) ",
+                LiveStatus::OpenEnded => format!(
+                    "({} {} ago @ {})",
+                    "STARTED?".if_supports_color(Stdout, |text| text.yellow().on_black()),
+                    compact_duration(since_start),
+                    date_time
+                ),
+                LiveStatus::Upcoming => format!(
+                    "({} in {} @ {})",
                     "UPCOMING".if_supports_color(Stdout, |text| text.white().on_black()),
+                    compact_duration(-since_start),
                     date_time
                 ),
+                LiveStatus::Ended => {
+                    let end = Zoned::new(self.end()?, TimeZone::system());
+                    let fmt = time_format.pattern(now.date() == end.date());
+                    let ended = format!("(ENDED @ {})", end.strftime(fmt));
+                    ended.if_supports_color(Stdout, |text| text.dimmed()).to_string()
+                }
             }
         } else {
-            format!("({}) ", date_time)
+            format!("({})", date_time)
+        };
+        let render = |title: &str| {
+            let line = fill_template(template, |token| {
+                Some(match token {
+                    "id" => self.proper_id().to_owned(),
+                    "url" => format!("https://www.cbc.ca/player/play/video/{}", self.proper_id()),
+                    "title" => title.to_owned(),
+                    "date" => date_time.clone(),
+                    "status" => status.map_or("", LiveStatus::label).to_owned(),
+                    "duration" => format_duration(self.media.duration),
+                    "note" => note.clone(),
+                    "cc" => self.captions_marker(),
+                    "show" => self.show_suffix(),
+                    _ => return None,
+                })
+            });
+            plain(&line).into_owned()
+        };
+        let title = plain(&self.title);
+        let line = render(&title);
+        match max_width {
+            Some(max) if display_width(&line) > max => {
+                let room = max.saturating_sub(display_width(&render(""))).max(MIN_TITLE_WIDTH);
+                Ok(render(&truncate(&title, room)))
+            }
+            _ => Ok(line),
+        }
+    }
+
+    /// ` [CC]` if the video has captions, or nothing.
+    fn captions_marker(&self) -> String {
+        if !self.media.has_captions {
+            return String::new();
+        }
+        format!(" {}", "[CC]".if_supports_color(Stdout, |text| text.cyan()))
+    }
+
+    /// The show name formatted to trail a listing line, or nothing if there isn't one.
+    fn show_suffix(&self) -> String {
+        let Some(name) = self.show_name.as_deref().map(str::trim).filter(|n| !n.is_empty()) else {
+            return String::new();
         };
-        let prefix = if full_urls { "https://www.cbc.ca/player/play/video/" } else { "" };
-        Ok(format!("{prefix}{} - {note}{}", self.proper_id(), self.title))
+        format!(
+            " — {}",
+            truncate(&plain(name), MAX_SHOW_NAME).if_supports_color(Stdout, |text| text.dimmed())
+        )
     }
 
-    pub(crate) fn timestamp(&self) -> Result<Timestamp> {
-        Ok(Timestamp::from_millisecond(self.published_at.parse()?)?)
+    /// When CBC last updated the node, in milliseconds. Unparseable values sort first.
+    pub fn updated(&self) -> i64 {
+        self.updated_at.parse().unwrap_or(i64::MIN)
     }
 
-    pub(crate) fn date(&self) -> Result<Zoned> {
+    pub fn timestamp(&self) -> Result<Timestamp, CbcError> {
+        let millis = self.published_at.parse().map_err(|e| CbcError::parse("publishedAt", e))?;
+        Timestamp::from_millisecond(millis).map_err(|e| CbcError::parse("publishedAt", e))
+    }
+
+    pub fn date(&self) -> Result<Zoned, CbcError> {
         Ok(Zoned::new(self.timestamp()?, TimeZone::system()))
     }
 
-    pub(crate) fn is_live(&self) -> Result<bool> {
-        let start = self.timestamp()?;
+    /// When the event is scheduled to end, based on its start time and duration.
+    pub fn end(&self) -> Result<Timestamp, CbcError> {
         let duration = self.media.duration.round() as i64;
         let duration = Span::new().seconds(duration);
-        let end = start.checked_add(duration)?;
+        self.timestamp()?.checked_add(duration).map_err(|e| CbcError::parse("duration", e))
+    }
+
+    /// Scheduled blocks that haven't aired yet sometimes have a zero or near-zero duration.
+    pub fn has_placeholder_duration(&self) -> bool {
+        self.media.duration < 60.0
+    }
+
+    pub fn status(&self) -> Result<LiveStatus, CbcError> {
         let now = Timestamp::now();
-        Ok(start <= now && now <= end)
+        Ok(if now < self.timestamp()? {
+            LiveStatus::Upcoming
+        } else if self.media.stream_type == StreamType::OnDemand {
+            // the live event has been turned into a replay
+            LiveStatus::Ended
+        } else if self.has_placeholder_duration() {
+            LiveStatus::OpenEnded
+        } else if now <= self.end()? {
+            LiveStatus::Live
+        } else {
+            LiveStatus::Ended
+        })
+    }
+
+    /// The widest of the image derivatives, if there are any.
+    pub fn largest_image(&self) -> Option<&Image> {
+        self.image.values().flatten().max_by_key(|image| image.w)
+    }
+
+    /// Whether this is a live event that's currently airing.
+    pub fn is_live(&self) -> Result<bool, CbcError> {
+        Ok(matches!(self.flag, Flag::Live)
+            && matches!(self.status()?, LiveStatus::Live | LiveStatus::OpenEnded))
+    }
+
+    /// Whether this is a live event whose scheduled window has passed.
+    pub fn has_ended(&self) -> Result<bool, CbcError> {
+        Ok(matches!(self.flag, Flag::Live) && self.status()? == LiveStatus::Ended)
     }
 }
 
-// #[derive(Debug, Clone, PartialEq, Deserialize)]
-// pub struct Category {
-//     pub(crate) name: String,
-//     pub(crate) slug: String,
-//     pub(crate) path: String,
-// }
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub enum LiveStatus {
+    Upcoming,
+    Live,
+    /// Past its start time, but the duration is a placeholder so we can't tell when it ends
+    OpenEnded,
+    Ended,
+}
+
+impl LiveStatus {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Upcoming => "UPCOMING",
+            Self::Live => "STARTED",
+            Self::OpenEnded => "STARTED?",
+            Self::Ended => "ENDED",
+        }
+    }
+}
+
+/// The listing line format used unless `--format` is given.
+pub const DEFAULT_FORMAT: &str = "{id} - {note}{cc} {title}{show}";
+/// [DEFAULT_FORMAT] with `--full-urls`.
+pub const FULL_URL_FORMAT: &str = "{url} - {note}{cc} {title}{show}";
+/// Show names wider than this many columns are cut short in listings.
+const MAX_SHOW_NAME: usize = 30;
+/// Titles are never shortened below this many columns, even if the line still wraps.
+const MIN_TITLE_WIDTH: usize = 20;
+
+/// Shorten `text` to at most `max` columns, ending with `…` (`...` with `--ascii`) if anything
+/// was cut. Cuts only between grapheme clusters, so accented letters are never split.
+pub fn truncate(text: &str, max: usize) -> String {
+    if text.width() <= max {
+        return text.to_owned();
+    }
+    let ellipsis = plain("…");
+    let mut out = String::new();
+    let mut width = 0;
+    for grapheme in text.graphemes(true) {
+        width += grapheme.width();
+        if width + ellipsis.width() > max {
+            break;
+        }
+        out.push_str(grapheme);
+    }
+    format!("{}{ellipsis}", out.trim_end())
+}
+
+static ASCII: AtomicBool = AtomicBool::new(false);
+
+/// For `--ascii`: from now on, [plain] turns everything into ASCII. Colors are turned off
+/// separately.
+pub fn set_ascii() {
+    ASCII.store(true, Ordering::Relaxed);
+}
+
+/// `text` unchanged, or with `--ascii`, transliterated to plain ASCII: accents are dropped
+/// (`Zürich` becomes `Zurich`), punctuation like `—` is spelled out and emoji become words.
+pub fn plain(text: &str) -> Cow<'_, str> {
+    if ASCII.load(Ordering::Relaxed) && !text.is_ascii() {
+        Cow::Owned(deunicode::deunicode(text))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+/// How many columns `text` takes up in a terminal, ignoring color codes.
+fn display_width(text: &str) -> usize {
+    regex!(r"\x1b\[[0-9;]*m").replace_all(text, "").width()
+}
+
+/// Replaces each `{token}` in `template` with the value `lookup` gives for it. Tokens that
+/// `lookup` doesn't know are left unchanged.
+fn fill_template(template: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        match after.find('}').and_then(|end| Some((end, lookup(&after[..end])?))) {
+            Some((end, value)) => {
+                out.push_str(&value);
+                rest = &after[end + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Clock style for times shown in listings.
+#[derive(Copy, Debug, Clone, PartialEq, Eq, ValueEnum)]
+pub enum TimeFormat {
+    #[value(name = "12")]
+    H12,
+    #[value(name = "24")]
+    H24,
+}
+
+impl TimeFormat {
+    /// The strftime pattern to use. Times on another day also show the date.
+    pub fn pattern(self, same_day: bool) -> &'static str {
+        match (self, same_day) {
+            (Self::H12, true) => "%I:%M %p",
+            (Self::H12, false) => "%b %d %I:%M %p",
+            (Self::H24, true) => "%H:%M",
+            (Self::H24, false) => "%b %d %H:%M",
+        }
+    }
+}
+
+fn millis_to_local(millis: &str) -> Option<Zoned> {
+    let timestamp = Timestamp::from_millisecond(millis.parse().ok()?).ok()?;
+    Some(Zoned::new(timestamp, TimeZone::system()))
+}
+
+/// Formats a number of seconds as e.g. `3h10m` or `47m`. Seconds are dropped and days are the
+/// largest unit, so far-off events read as `12d4h`.
+pub(crate) fn compact_duration(seconds: i64) -> String {
+    let minutes = seconds.max(0) / 60;
+    let (days, hours, minutes) = (minutes / 1440, minutes / 60 % 24, minutes % 60);
+    if days > 0 {
+        format!("{days}d{hours}h")
+    } else if hours > 0 {
+        format!("{hours}h{minutes}m")
+    } else {
+        format!("{minutes}m")
+    }
+}
+
+/// Formats a video's length, e.g. `2h15m` or `1m35s`: the two largest units, from days down to
+/// seconds. Zero and negative lengths (unknown, for most live events) are shown as `--`.
+pub fn format_duration(seconds: f64) -> String {
+    // round once up front so a remainder can never round up to a whole unit, like 1h60m
+    let seconds = seconds.round() as i64;
+    if seconds <= 0 {
+        return "--".to_owned();
+    }
+    let (days, hours) = (seconds / 86400, seconds / 3600 % 24);
+    let (minutes, seconds) = (seconds / 60 % 60, seconds % 60);
+    if days > 0 {
+        format!("{days}d{hours}h")
+    } else if hours > 0 {
+        format!("{hours}h{minutes}m")
+    } else if minutes > 0 {
+        format!("{minutes}m{seconds}s")
+    } else {
+        format!("{seconds}s")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Category {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub slug: Option<String>,
+}
 
-#[derive(Copy, Debug, Clone, PartialEq, Deserialize)]
+/// What kind of listing entry a [Node] is.
+#[derive(Copy, Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all(serialize = "snake_case"))]
 pub enum Flag {
     Live,
     Video,
+    /// A kind CBC has added since this version of cbc-sl. Listed like a replay rather than
+    /// breaking the whole listing
+    #[serde(other)]
+    Unknown,
 }
 
-// #[derive(Debug, Clone, PartialEq, Deserialize)]
-// pub struct Image {
-//     pub(crate) w: i64,
-//     pub(crate) fileurl: String,
-// }
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Image {
+    /// Width in pixels
+    pub w: i64,
+    pub fileurl: String,
+}
 
-#[derive(Debug, Clone, PartialEq, Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all(deserialize = "camelCase", serialize = "snake_case"))]
 pub struct Media {
-    pub(crate) duration: f64,
-    pub(crate) has_captions: bool,
-    pub(crate) stream_type: StreamType,
+    /// Length in seconds. Live events that haven't aired yet often have a placeholder of zero
+    /// or close to it
+    pub duration: f64,
+    pub has_captions: bool,
+    pub stream_type: StreamType,
 }
 
-#[derive(Debug, Clone, PartialEq, Deserialize)]
+/// Whether a video streams live or on demand. Live events become on demand after they air.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all(serialize = "snake_case"))]
 pub enum StreamType {
     Live,
-    #[serde(rename = "On-Demand")]
+    #[serde(rename(deserialize = "On-Demand"))]
     OnDemand,
+    /// A type CBC has added since this version of cbc-sl
+    #[serde(other)]
+    Unknown,
 }
 
 // #[derive(Debug, Clone, PartialEq, Deserialize)]
@@ -156,6 +572,8 @@ pub enum StreamType {
 //     pub(crate) warnings: Vec<String>,
 // }
 
+/// The `window.__INITIAL_STATE__` object from a player page (`https://www.cbc.ca/player/play/
+/// video/ID`), found with [find_initial_state]. This mirrors CBC's site and may break.
 #[derive(Debug, Clone, PartialEq, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct InitialState {
@@ -189,7 +607,7 @@ pub struct InitialState {
     // pub(crate) subject_content: SubjectContent,
     // pub(crate) tracking: InitialStateTracking,
     // pub(crate) trending: Trending,
-    pub(crate) video: Video,
+    pub video: Video,
     // pub(crate) video_detail: VideoDetail,
     // pub(crate) weather: Weather,
 }
@@ -197,74 +615,213 @@ pub struct InitialState {
 #[derive(Debug, Clone, PartialEq, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Video {
-    pub(crate) current_clip: CurrentClip,
-    // pub(crate) recommendations: CuratedPlaylist,
+    pub current_clip: CurrentClip,
+    #[serde(default, deserialize_with = "lenient")]
+    pub recommendations: CuratedPlaylist,
     // pub(crate) trending_clips: CuratedPlaylist,
     // pub(crate) curated_playlist: CuratedPlaylist,
     // pub(crate) more_from_base_section: CuratedPlaylist,
 }
 
 impl Video {
-    pub(crate) fn get_stream_urls(&self) -> StreamURLs {
-        let mut urls = StreamURLs { dai: None, medianet: None };
-        for surl in &self.current_clip.media.assets {
-            if surl.asset_type == "platform-dai" {
-                // TODO https://pubads.g.doubleclick.net
-                //  it requires a bit more work but I don't know if medianet is always present
-            } else if surl.asset_type == "medianet" {
-                urls.medianet = Some(surl.key.to_string());
-            }
+    /// The first asset of type `asset_type`, normally `medianet`. If there isn't one, the error
+    /// lists the types that are there.
+    pub fn asset(&self, asset_type: &str) -> Result<&Asset, CbcError> {
+        let assets = &self.current_clip.media.assets;
+        for asset in assets {
+            debug!("asset type={}", asset.asset_type);
         }
-        urls
+        assets.iter().find(|asset| asset.asset_type == asset_type).ok_or_else(|| {
+            let available: Vec<_> = assets.iter().map(|a| a.asset_type.as_str()).collect();
+            let available =
+                if available.is_empty() { "none".to_owned() } else { available.join(", ") };
+            CbcError::NotFound {
+                what: format!("no {asset_type} asset found; this video has: {available}"),
+            }
+        })
+    }
+
+    /// The first recommended clip that isn't the current one.
+    pub fn next_clip(&self) -> Option<&RecommendedClip> {
+        self.recommendations.items.iter().find(|clip| clip.id != self.current_clip.source_id)
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub(crate) struct StreamURLs {
-    pub(crate) dai: Option<String>,
-    pub(crate) medianet: Option<String>,
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct CuratedPlaylist {
+    #[serde(default)]
+    pub items: Vec<RecommendedClip>,
 }
 
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecommendedClip {
+    pub id: String,
+    pub title: String,
+}
+
+/// Deserializes `T`, falling back to its default if the data doesn't have the expected shape.
+/// For optional parts of the page that shouldn't stop playback if CBC changes them.
+fn lenient<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned + Default,
+{
+    let value = serde_json::Value::deserialize(deserializer)?;
+    Ok(serde_json::from_value(value).unwrap_or_default())
+}
+
+/// The video a player page is for.
 #[derive(Debug, Clone, PartialEq, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CurrentClip {
-    pub(crate) source_id: String,
+    /// The video's ID, as in player URLs
+    pub source_id: String,
     // pub(crate) media_id: Option<serde_json::Value>,
-    pub(crate) source: String,
-    pub(crate) title: String,
+    pub source: String,
+    pub title: String,
     // pub(crate) image: Image,
-    pub(crate) published_at: String,
+    pub published_at: String,
     // #[serde(rename = "type")]
     // pub(crate) current_clip_type: StreamType,
     // pub(crate) show_data: Option<serde_json::Value>,
-    // pub(crate) show_name: Option<serde_json::Value>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub show_name: Option<String>,
     // pub(crate) tags: Vec<Tag>,
     // pub(crate) concepts: Vec<Option<serde_json::Value>>,
-    pub(crate) media: CurrentClipMedia,
-    pub(crate) updated_at: String,
-    pub(crate) description: String,
+    pub media: CurrentClipMedia,
+    pub updated_at: String,
+    pub description: String,
     // pub(crate) categories: Vec<Category2>,
     // pub(crate) section: Option<serde_json::Value>,
 }
 
+impl CurrentClip {
+    /// When the clip was published, or `None` if CBC sent something unparseable.
+    pub fn published(&self) -> Option<Zoned> {
+        millis_to_local(&self.published_at)
+    }
+
+    pub fn updated(&self) -> Option<Zoned> {
+        millis_to_local(&self.updated_at)
+    }
+
+    /// Whether the clip should still be airing, going by its start time and duration. Live
+    /// clips without a real duration yet are assumed to still be on.
+    pub fn should_be_live(&self) -> bool {
+        let Ok(start) = self.published_at.parse::<i64>() else { return false };
+        let now = Timestamp::now().as_millisecond();
+        start <= now && (self.media.duration < 60 || now <= start + self.media.duration * 1000)
+    }
+
+    /// A file name for recording the clip, from a `--output-template` like
+    /// [DEFAULT_OUTPUT_TEMPLATE]. The tokens are `{title}`, `{id}`, `{date}` (when it was
+    /// published, `YYYY-MM-DD`) and `{ext}`. Their values are made safe to use in a file name;
+    /// the rest of the template is used as-is, so it can put recordings in a directory.
+    pub fn file_name(&self, template: &str) -> String {
+        let date = self.published().unwrap_or_else(Zoned::now).strftime("%Y-%m-%d").to_string();
+        fill_template(template, |token| {
+            Some(match token {
+                "title" => sanitize_file_name(&self.title),
+                "id" => sanitize_file_name(&self.source_id),
+                "date" => date.clone(),
+                "ext" => "ts".to_owned(),
+                _ => return None,
+            })
+        })
+    }
+}
+
+/// What `--output-template` uses when it's given without a template.
+pub const DEFAULT_OUTPUT_TEMPLATE: &str = "{date}_{title}.{ext}";
+
+/// Longest a value put in a file name may be, in characters, leaving room for the rest of the
+/// template within common 255-byte limits.
+const MAX_FILE_NAME_VALUE: usize = 120;
+
+/// Make `value` safe to use in a file name on any OS: characters Windows forbids (including
+/// path separators) become `_`, as do control characters, and it's shortened to
+/// [MAX_FILE_NAME_VALUE]. Windows also can't have names ending in a dot or space, or devices
+/// like `CON`.
+fn sanitize_file_name(value: &str) -> String {
+    let replaced: String = value
+        .chars()
+        .map(|c| if c.is_control() || r#"<>:"/\|?*"#.contains(c) { '_' } else { c })
+        .collect();
+    let mut name: String = replaced.trim().chars().take(MAX_FILE_NAME_VALUE).collect();
+    name.truncate(name.trim_end_matches(['.', ' ']).len());
+    let stem = name.split('.').next().unwrap_or_default().to_ascii_uppercase();
+    let reserved = matches!(stem.as_str(), "CON" | "PRN" | "AUX" | "NUL")
+        || (stem.len() == 4
+            && (stem.starts_with("COM") || stem.starts_with("LPT"))
+            && stem.ends_with(|c: char| c.is_ascii_digit()));
+    if name.is_empty() || reserved {
+        name.insert(0, '_');
+    }
+    name
+}
+
+/// What `--print-metadata --json` prints about a clip. Like [Node], the field names are a stable
+/// contract.
+#[derive(Debug, Serialize)]
+pub struct ClipMetadata<'a> {
+    pub id: &'a str,
+    pub title: &'a str,
+    pub show_name: Option<&'a str>,
+    pub description: &'a str,
+    /// RFC 3339, in UTC
+    pub published_at: Option<String>,
+    /// RFC 3339, in UTC
+    pub updated_at: Option<String>,
+    /// In seconds
+    pub duration: i64,
+    pub duration_text: String,
+    pub stream_type: Option<&'a StreamType>,
+    /// The asset types, e.g. `medianet`
+    pub assets: Vec<&'a str>,
+}
+
+impl CurrentClip {
+    pub fn metadata(&self) -> ClipMetadata<'_> {
+        ClipMetadata {
+            id: &self.source_id,
+            title: &self.title,
+            show_name: self.show_name.as_deref(),
+            description: &self.description,
+            published_at: self.published().map(|t| t.timestamp().to_string()),
+            updated_at: self.updated().map(|t| t.timestamp().to_string()),
+            duration: self.media.duration,
+            duration_text: format_duration(self.media.duration as f64),
+            stream_type: self.media.stream_type.as_ref(),
+            assets: self.media.assets.iter().map(|a| a.asset_type.as_str()).collect(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CurrentClipMedia {
-    pub(crate) id: i64,
+    pub id: i64,
     // pub(crate) call_sign: Option<serde_json::Value>,
-    pub(crate) assets: Vec<Asset>,
+    pub assets: Vec<Asset>,
     // pub(crate) ad_order: String,
     // pub(crate) ad_category_exclusion: Option<serde_json::Value>,
-    // pub(crate) stream_type: StreamType,
+    #[serde(default, deserialize_with = "lenient")]
+    pub stream_type: Option<StreamType>,
     // pub(crate) content_area: String,
-    // pub(crate) content_tier_id: i64,
-    pub(crate) duration: i64,
+    /// Who can watch it: 1 for everyone, higher for member-only or premium content. See
+    /// [CurrentClipMedia::restrictions]
+    #[serde(default, deserialize_with = "lenient")]
+    pub content_tier_id: Option<i64>,
+    pub duration: i64,
     // pub(crate) genre: Option<serde_json::Value>,
     // pub(crate) clip_type: String,
     // pub(crate) branded_sponsor_name: String,
     // pub(crate) season: Option<serde_json::Value>,
     // pub(crate) episode: Option<serde_json::Value>,
-    // pub(crate) region: String,
+    /// Where it can be watched, like `CA`, or empty if anywhere
+    #[serde(default, deserialize_with = "lenient")]
+    pub region: Option<String>,
     // pub(crate) sports: Spo,
     // pub(crate) has_captions: bool,
     // pub(crate) aspect_ratio: String,
@@ -273,12 +830,42 @@ pub struct CurrentClipMedia {
     // pub(crate) exclude_from_recommendations: Option<serde_json::Value>,
 }
 
+impl CurrentClipMedia {
+    /// What CBC has marked as limiting who can watch this, like `region: CA, tier: premium`, or
+    /// `None` if it's open to everyone. Fetching the stream will likely fail from outside the
+    /// region, or without the right account for the tier.
+    pub fn restrictions(&self) -> Option<String> {
+        let mut marks = Vec::new();
+        if let Some(region) = self.region.as_deref().map(str::trim).filter(|r| is_restricted(r)) {
+            marks.push(format!("region: {region}"));
+        }
+        match self.content_tier_id {
+            None | Some(..=1) => {}
+            Some(2) => marks.push("tier: member".to_owned()),
+            Some(3) => marks.push("tier: premium".to_owned()),
+            Some(tier) => marks.push(format!("tier: {tier}")),
+        }
+        (!marks.is_empty()).then(|| marks.join(", "))
+    }
+}
+
+/// Whether a `region` limits where a video can be watched, rather than meaning anywhere.
+fn is_restricted(region: &str) -> bool {
+    !region.is_empty()
+        && !["all", "ww", "world", "worldwide", "international"]
+            .iter()
+            .any(|open| region.eq_ignore_ascii_case(open))
+}
+
+/// Somewhere the video can be fetched from.
 #[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct Asset {
-    pub(crate) key: String,
+    /// A URL. For `medianet` assets, fetching it gives a [Stream]
+    pub key: String,
     #[serde(rename = "type")]
-    pub(crate) asset_type: String,
-    // pub(crate) options: Option<serde_json::Value>,
+    pub asset_type: String,
+    #[serde(default, deserialize_with = "lenient")]
+    pub options: Option<serde_json::Value>,
 }
 
 // #[derive(Debug, Clone, PartialEq, Deserialize)]
@@ -287,16 +874,159 @@ pub struct Asset {
 //     pub(crate) slug: String,
 // }
 
+/// The JSON a `medianet` [Asset] URL returns. Read it with [Stream::from_response].
 #[derive(Debug, Clone, PartialEq, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Stream {
-    pub(crate) url: String,
+    /// The HLS master playlist. It has a short-lived token in it
+    pub url: String,
     // pub(crate) message: Option<serde_json::Value>,
-    pub(crate) error_code: i64,
-    pub(crate) params: Vec<Param>,
+    #[serde(default)]
+    pub error_code: i64,
+    #[serde(default)]
+    pub params: Vec<Param>,
     // pub(crate) bitrates: Vec<Bitrate>,
 }
 
+/// Find the `__INITIAL_STATE__` JSON in a player page. CBC's markup has varied over the years,
+/// so this tries the usual assignment, then scans for the end of the object by matching braces,
+/// then looks for it in a JSON `<script>` tag. Only candidates that are valid JSON are returned.
+pub fn find_initial_state(page: &str) -> Option<&str> {
+    let is_json =
+        |candidate: &str| serde_json::from_str::<serde::de::IgnoredAny>(candidate).is_ok();
+    let assignments = [
+        regex!(r#"window\.__INITIAL_STATE__ = (.*);</script>"#),
+        regex!(r#"(?s)window\.__INITIAL_STATE__\s*=\s*(\{.*?\})\s*;?\s*</script>"#),
+    ];
+    for (i, regex) in assignments.iter().enumerate() {
+        if let Some(found) = regex.captures(page).map(|c| c.get(1).unwrap().as_str()) {
+            if is_json(found) {
+                debug!("initial_state method=regex{i}");
+                return Some(found);
+            }
+            debug!("initial_state method=regex{i} result=invalid_json");
+        }
+    }
+    if let Some(found) = page.find("__INITIAL_STATE__").and_then(|at| balanced_object(&page[at..]))
+    {
+        if is_json(found) {
+            debug!("initial_state method=brace_scan");
+            return Some(found);
+        }
+        debug!("initial_state method=brace_scan result=invalid_json");
+    }
+    let script = regex!(r#"(?s)<script[^>]*type="application/json"[^>]*>(.*?)</script>"#);
+    for found in script.captures_iter(page).map(|c| c.get(1).unwrap().as_str().trim()) {
+        let has_video = serde_json::from_str::<serde_json::Value>(found)
+            .is_ok_and(|value| value.get("video").is_some());
+        if has_video {
+            debug!("initial_state method=json_script");
+            return Some(found);
+        }
+    }
+    debug!("initial_state result=not_found");
+    None
+}
+
+/// The first `{...}` object in `text`, found by counting braces outside of strings.
+fn balanced_object(text: &str) -> Option<&str> {
+    let start = text.find('{')?;
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in text[start..].char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(&text[start..start + i + 1]);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// medianet's `errorCode` when the stream isn't available from where we are.
+const MEDIANET_GEO_BLOCKED: i64 = 1;
+/// medianet's `errorCode` when the stream needs a signed-in CBC account.
+const MEDIANET_SIGN_IN: i64 = 35;
+
+/// The fields medianet fills in when it fails. Successful responses have them too, with an
+/// `errorCode` of 0.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MedianetError {
+    #[serde(default)]
+    error_code: i64,
+    #[serde(default)]
+    message: Option<String>,
+}
+
+impl Stream {
+    /// Parse a medianet response. Error bodies and HTML error pages become errors with the
+    /// status and the start of the body, rather than a serde error about a missing `url`.
+    pub fn from_response(status: u16, content_type: &str, body: &str) -> Result<Self, CbcError> {
+        if let Ok(error) = serde_json::from_str::<MedianetError>(body) {
+            if error.error_code != 0 {
+                let code = error.error_code;
+                let message = error.message.as_deref().unwrap_or("no message");
+                if code == MEDIANET_SIGN_IN || mentions_sign_in(message) {
+                    return Err(CbcError::RequiresAccount);
+                }
+                if code == MEDIANET_GEO_BLOCKED
+                    || matches!(status, 403 | 451)
+                    || mentions_region(message)
+                {
+                    let reason = format!("medianet error {code} (HTTP {status}): {message}");
+                    return Err(CbcError::GeoBlocked { reason });
+                }
+                let message = message.to_owned();
+                return Err(CbcError::Medianet { code, status, message });
+            }
+        }
+        if (200..300).contains(&status) {
+            if let Ok(stream) = serde_json::from_str(body) {
+                return Ok(stream);
+            }
+        }
+        let snippet: String = body.split_whitespace().collect::<Vec<_>>().join(" ");
+        let context = format!(
+            "unexpected medianet response (HTTP {status}, {content_type}): {}",
+            truncate(&snippet, 200)
+        );
+        Err(CbcError::ApiChanged { context })
+    }
+}
+
+/// Whether a medianet error message is about where we are.
+fn mentions_region(message: &str) -> bool {
+    let message = message.to_ascii_lowercase();
+    ["geo", "region", "country", "territor", "location"]
+        .iter()
+        .any(|marker| message.contains(marker))
+}
+
+/// Whether a medianet error message is about needing to sign in, rather than where we are.
+fn mentions_sign_in(message: &str) -> bool {
+    let message = message.to_ascii_lowercase();
+    ["login", "log in", "sign in", "authenticat", "claims", "connexion"]
+        .iter()
+        .any(|marker| message.contains(marker))
+}
+
 // #[derive(Debug, Clone, PartialEq, Deserialize)]
 // pub struct Bitrate {
 //     pub(crate) bitrate: i64,
@@ -309,8 +1039,8 @@ pub struct Stream {
 
 #[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct Param {
-    pub(crate) name: String,
-    pub(crate) value: Value,
+    pub name: String,
+    pub value: Value,
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize)]
@@ -319,3 +1049,293 @@ pub enum Value {
     Integer(i64),
     String(String),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A listing node as CBC's GraphQL sends it, trimmed to the fields we read.
+    fn node_json(id: i64, published_at: &str) -> serde_json::Value {
+        serde_json::json!({
+            "id": id,
+            "url": format!("https://www.cbc.ca/player/play/video/1.72763{id:02}"),
+            "title": format!("Event {id}"),
+            "flag": "Live",
+            "publishedAt": published_at,
+            "updatedAt": "1722000000000",
+            "type": "video",
+            "media": {"duration": 5400, "hasCaptions": true, "streamType": "Live"}
+        })
+    }
+
+    /// A full listing node, as captured from CBC's GraphQL.
+    const NODE: &str = r#"{
+        "id": 9876543,
+        "url": "https://www.cbc.ca/player/play/video/1.7276395",
+        "title": "Diving: Women's 3m springboard final",
+        "sectionList": ["Sports", "Olympics"],
+        "description": "Watch the final live.",
+        "flag": "Live",
+        "image": {"16x9": {"w": 620, "fileurl": "https://i.cbc.ca/diving.jpg"}, "4x3": null},
+        "sourceId": "2347123456789",
+        "publishedAt": "1722000000000",
+        "updatedAt": "1722000060000",
+        "type": "video",
+        "showName": "Paris 2024",
+        "media": {"duration": 5400, "hasCaptions": true, "streamType": "Live"},
+        "categories": [{"name": "Diving", "slug": "diving"}]
+    }"#;
+
+    #[test]
+    fn node_json_shape() {
+        let node: Node = serde_json::from_str(NODE).unwrap();
+        let expected = serde_json::json!({
+            "id": 9876543,
+            "url": "https://www.cbc.ca/player/play/video/1.7276395",
+            "title": "Diving: Women's 3m springboard final",
+            "section_list": ["Sports", "Olympics"],
+            "description": "Watch the final live.",
+            "flag": "live",
+            "image": {"16x9": {"w": 620, "fileurl": "https://i.cbc.ca/diving.jpg"}, "4x3": null},
+            "source_id": "2347123456789",
+            "published_at": "1722000000000",
+            "updated_at": "1722000060000",
+            "type": "video",
+            "show_name": "Paris 2024",
+            "media": {
+                "duration": 5400.0,
+                "has_captions": true,
+                "stream_type": "live",
+                "duration_text": "1h30m"
+            },
+            "categories": [{"name": "Diving", "slug": "diving"}]
+        });
+        assert_eq!(node.to_json(true).unwrap(), expected);
+
+        let mut short = expected;
+        for field in ["description", "categories", "section_list"] {
+            short.as_object_mut().unwrap().remove(field);
+        }
+        assert_eq!(node.to_json(false).unwrap(), short);
+    }
+
+    #[test]
+    fn one_bad_node_is_skipped() {
+        let mut bad = node_json(2, "1722000000000");
+        bad.as_object_mut().unwrap().remove("media");
+        let values = vec![
+            node_json(1, "1722000000000"),
+            bad,
+            node_json(3, "not a date"),
+            node_json(4, "1722000000000"),
+        ];
+        let body = serde_json::json!({"data": {"allContentItems": {"nodes": values}}});
+        let response: GqlResponse = serde_json::from_value(body).unwrap();
+        let items = response.data.all_content_items;
+        let ids: Vec<i64> = items.nodes.iter().map(|node| node.id).collect();
+        assert_eq!(ids, [1, 4]);
+        assert_eq!(items.skipped, ["2", "3"]);
+        assert_eq!(items.received, 4);
+    }
+
+    #[test]
+    fn medianet_success() {
+        let body = r#"{"url": "https://cbcrcott.akamaized.net/master.m3u8?hdnea=x", "errorCode": 0, "params": []}"#;
+        let stream = Stream::from_response(200, "application/json", body).unwrap();
+        assert_eq!(stream.url, "https://cbcrcott.akamaized.net/master.m3u8?hdnea=x");
+    }
+
+    #[test]
+    fn medianet_html_error_page() {
+        let body = "<html><head><title>502 Bad Gateway</title></head>\n<body>nginx</body></html>";
+        let error = Stream::from_response(502, "text/html", body).unwrap_err();
+        let CbcError::ApiChanged { context } = &error else { panic!("{error:?}") };
+        assert!(context.contains("HTTP 502, text/html"), "{context}");
+        assert!(context.contains("502 Bad Gateway"), "{context}");
+    }
+
+    #[test]
+    fn medianet_error_json() {
+        let geo =
+            r#"{"errorCode": 1, "message": "This content is not available in your location"}"#;
+        let error = Stream::from_response(200, "application/json", geo).unwrap_err();
+        assert!(matches!(error, CbcError::GeoBlocked { .. }), "{error:?}");
+
+        let sign_in = r#"{"errorCode": 35, "message": "Unauthorized"}"#;
+        let error = Stream::from_response(200, "application/json", sign_in).unwrap_err();
+        assert!(matches!(error, CbcError::RequiresAccount), "{error:?}");
+
+        let gone = r#"{"errorCode": 12, "message": "Content not found"}"#;
+        let error = Stream::from_response(200, "application/json", gone).unwrap_err();
+        let CbcError::Medianet { code: 12, status: 200, message } = &error else {
+            panic!("{error:?}")
+        };
+        assert_eq!(message, "Content not found");
+        assert_eq!(error.code(), None);
+    }
+
+    /// A live node that started `ago` seconds ago (negative for the future), lasting `duration`.
+    fn live_node(ago: i64, duration: f64, stream_type: &str) -> Node {
+        let start = Timestamp::now().as_millisecond() - ago * 1000;
+        let mut value = node_json(1, &start.to_string());
+        value["media"]["duration"] = serde_json::json!(duration);
+        value["media"]["streamType"] = serde_json::json!(stream_type);
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn placeholder_duration_is_open_ended() {
+        let node = live_node(600, 0.0, "Live");
+        assert!(node.has_placeholder_duration());
+        assert_eq!(node.status().unwrap(), LiveStatus::OpenEnded);
+        assert_eq!(node.status().unwrap().label(), "STARTED?");
+        assert!(node.is_live().unwrap());
+        // hours later, a placeholder still can't say it's over
+        assert_eq!(live_node(6 * 3600, 30.0, "Live").status().unwrap(), LiveStatus::OpenEnded);
+        // but not before it starts, or once it's a replay
+        assert_eq!(live_node(-3600, 0.0, "Live").status().unwrap(), LiveStatus::Upcoming);
+        assert_eq!(live_node(600, 0.0, "On-Demand").status().unwrap(), LiveStatus::Ended);
+    }
+
+    #[test]
+    fn real_duration_sets_the_window() {
+        assert_eq!(live_node(600, 5400.0, "Live").status().unwrap(), LiveStatus::Live);
+        assert_eq!(live_node(600, 5400.0, "Live").status().unwrap().label(), "STARTED");
+        assert_eq!(live_node(3 * 3600, 5400.0, "Live").status().unwrap(), LiveStatus::Ended);
+        assert!(live_node(3 * 3600, 5400.0, "Live").has_ended().unwrap());
+    }
+
+    #[test]
+    fn current_clip_with_placeholder_should_be_live() {
+        let clip = |ago: i64, duration: i64| -> CurrentClip {
+            let start = Timestamp::now().as_millisecond() - ago * 1000;
+            serde_json::from_value(serde_json::json!({
+                "sourceId": "1.7276395",
+                "source": "cbc",
+                "title": "Diving",
+                "publishedAt": start.to_string(),
+                "updatedAt": start.to_string(),
+                "description": "",
+                "media": {"id": 1, "duration": duration, "assets": []}
+            }))
+            .unwrap()
+        };
+        assert!(clip(600, 0).should_be_live());
+        assert!(clip(6 * 3600, 0).should_be_live());
+        assert!(!clip(-600, 0).should_be_live());
+        assert!(clip(600, 5400).should_be_live());
+        assert!(!clip(3 * 3600, 5400).should_be_live());
+    }
+
+    /// The player state each page shape below carries, one way or another.
+    const STATE: &str = r#"{"video":{"currentClip":{"title":"A \"quoted\" } brace"}}}"#;
+
+    fn state_of(page: &str) -> serde_json::Value {
+        let found = find_initial_state(page).expect("no initial state found");
+        serde_json::from_str(found).unwrap()
+    }
+
+    #[test]
+    fn initial_state_on_one_line() {
+        let page = format!(
+            "<html><head><script>window.__INITIAL_STATE__ = {STATE};</script></head></html>"
+        );
+        assert_eq!(find_initial_state(&page), Some(STATE));
+    }
+
+    #[test]
+    fn initial_state_with_other_whitespace() {
+        let page = format!(
+            "<script>\n  window.__INITIAL_STATE__={STATE}\n</script>\n<script>var x = 1;</script>"
+        );
+        assert_eq!(find_initial_state(&page), Some(STATE));
+    }
+
+    #[test]
+    fn initial_state_followed_by_another_assignment() {
+        let page = format!(
+            "<script>window.__INITIAL_STATE__ = {STATE}; window.__FLAGS__ = {{\"a\":1}};</script>"
+        );
+        assert_eq!(find_initial_state(&page), Some(STATE));
+    }
+
+    #[test]
+    fn initial_state_in_json_script() {
+        let page = format!(
+            "<script type=\"application/json\" id=\"config\">{{\"env\":\"prod\"}}</script>\n\
+            <script id=\"__INITIAL_DATA__\" type=\"application/json\">\n{STATE}\n</script>"
+        );
+        assert_eq!(state_of(&page), serde_json::from_str::<serde_json::Value>(STATE).unwrap());
+    }
+
+    #[test]
+    fn initial_state_missing() {
+        let page = "<html><script>window.__OTHER__ = {\"video\": 1};</script></html>";
+        assert_eq!(find_initial_state(page), None);
+        let truncated = "<script>window.__INITIAL_STATE__ = {\"video\":{\"a\":1}";
+        assert_eq!(find_initial_state(truncated), None);
+    }
+
+    #[test]
+    fn truncate_keeps_characters_whole() {
+        let cases = [
+            // exactly the limit, accents and all, is left alone
+            ("Québec", 6, "Québec"),
+            ("Québec", 5, "Québ…"),
+            // an accented letter right at the cut is kept or dropped whole
+            ("Café crème", 5, "Café…"),
+            ("Café crème", 4, "Caf…"),
+            // the same, with the accents as combining characters
+            ("Cafe\u{301} cre\u{300}me", 5, "Cafe\u{301}…"),
+            ("Cafe\u{301} cre\u{300}me", 4, "Caf…"),
+            ("Cre\u{300}me", 5, "Cre\u{300}me"),
+        ];
+        for (text, max, expected) in cases {
+            let truncated = truncate(text, max);
+            assert_eq!(truncated, expected, "{text:?} to {max}");
+            assert!(truncated.width() <= max, "{truncated:?} is wider than {max}");
+        }
+    }
+
+    #[test]
+    fn format_duration_boundaries() {
+        let cases = [
+            (-5.0, "--"),
+            (0.0, "--"),
+            (0.4, "--"),
+            (0.6, "1s"),
+            (59.0, "59s"),
+            (59.6, "1m0s"),
+            (60.0, "1m0s"),
+            (95.0, "1m35s"),
+            (3599.0, "59m59s"),
+            (3599.5, "1h0m"),
+            (3600.0, "1h0m"),
+            (8123.4, "2h15m"),
+            (86399.0, "23h59m"),
+            (86400.0, "1d0h"),
+            (90000.0, "1d1h"),
+        ];
+        for (seconds, text) in cases {
+            assert_eq!(format_duration(seconds), text, "{seconds}");
+        }
+    }
+
+    #[test]
+    fn compact_duration_boundaries() {
+        let cases = [
+            (-60, "0m"),
+            (0, "0m"),
+            (59, "0m"),
+            (60, "1m"),
+            (3599, "59m"),
+            (3600, "1h0m"),
+            (86399, "23h59m"),
+            (86400, "1d0h"),
+            (1_051_200, "12d4h"),
+        ];
+        for (seconds, text) in cases {
+            assert_eq!(compact_duration(seconds), text, "{seconds}");
+        }
+    }
+}
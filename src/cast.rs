@@ -0,0 +1,37 @@
+use std::process::Command;
+
+use anyhow::{anyhow, bail, Context, Result};
+use ureq::AgentBuilder;
+
+use crate::debug::debug;
+
+/// Cast `url` to a Chromecast with catt. The Chromecast fetches the stream itself, without our
+/// User-Agent, Referer or proxy, so first check that the playlist can be fetched that way. If it
+/// can't, there's no fallback: cbc-sl has no relay mode to re-serve the stream locally with the
+/// headers added.
+pub(crate) fn cast(url: &str, device: Option<&str>, proxied: bool) -> Result<()> {
+    if proxied {
+        eprintln!("Note: the Chromecast connects directly and won't use your proxy.");
+    }
+    // Chromecasts send their own User-Agent; anything other than ours will do for the check
+    let agent = AgentBuilder::new().user_agent("cbc-sl").build();
+    debug!("cast check url={url}");
+    if let Err(e) = agent.get(url).call() {
+        bail!(
+            "the stream can't be fetched without cbc-sl's headers ({e}), so a Chromecast won't be \
+            able to play it. cbc-sl can't re-serve the stream locally with the headers added (it \
+            has no relay mode), so --cast can't be used for this stream; try `cbc-sl url` and a \
+            player on this machine instead"
+        );
+    }
+    let mut cmd = Command::new("catt");
+    if let Some(device) = device {
+        cmd.arg("-d").arg(device);
+    }
+    // catt's own output (including device discovery errors) goes straight to the terminal
+    let status = cmd.arg("cast").arg(url).status().context("running catt")?;
+    if !status.success() {
+        return Err(anyhow!("catt exited with {status}"));
+    }
+    Ok(())
+}
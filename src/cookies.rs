@@ -0,0 +1,437 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use jiff::Timestamp;
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use ureq::{Middleware, MiddlewareNext, Request, Response};
+use url::Url;
+
+use crate::debug::debug;
+
+static JAR: OnceCell<Jar> = OnceCell::new();
+
+/// The cookies for this run: any loaded from `--cookies`, plus those CBC sets as we go, so later
+/// requests (and streamlink) send what the player page was given.
+#[derive(Debug, Default)]
+pub(crate) struct Jar {
+    cookies: Mutex<Vec<Cookie>>,
+    /// Where to save the cookies when done, and in which format
+    file: Option<(PathBuf, Format)>,
+    /// Whether CBC has set or removed anything since loading
+    changed: AtomicBool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Cookie {
+    domain: String,
+    /// Sent to subdomains too, rather than only `domain` itself
+    #[serde(skip)]
+    include_subdomains: bool,
+    /// The JSON exports' inverse of `include_subdomains`, only used when reading and writing them
+    #[serde(default)]
+    host_only: bool,
+    path: String,
+    secure: bool,
+    #[serde(default)]
+    http_only: bool,
+    name: String,
+    value: String,
+    /// Seconds since the Unix epoch, or `None` for a session cookie
+    #[serde(default, rename = "expirationDate", skip_serializing_if = "Option::is_none")]
+    expires: Option<f64>,
+}
+
+/// The formats `--cookies` can be in.
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+enum Format {
+    /// `cookies.txt`, as exported by browser extensions, curl and yt-dlp
+    Netscape,
+    /// A JSON array, as exported by extensions like Cookie-Editor
+    Json,
+}
+
+/// Start the jar, loading the `--cookies` file if there is one. Cookies CBC sets are saved back
+/// to that file by [Jar::save]. Expired cookies are skipped. Values are never logged.
+pub(crate) fn init(path: Option<&Path>) -> Result<&'static Jar> {
+    let mut jar = Jar::default();
+    if let Some(path) = path {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            // it'll be created when saving
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+        };
+        let format =
+            if contents.trim_start().starts_with('[') { Format::Json } else { Format::Netscape };
+        let cookies = match format {
+            Format::Netscape => parse_netscape(&contents),
+            Format::Json => parse_json(&contents)
+                .with_context(|| format!("reading {} as JSON", path.display()))?,
+        };
+        debug!("cookies loaded count={} file={}", cookies.len(), path.display());
+        jar.cookies = Mutex::new(cookies);
+        jar.file = Some((path.to_owned(), format));
+    }
+    Ok(JAR.get_or_init(|| jar))
+}
+
+/// The cookie jar, unless cookies are turned off with `--no-cookies`.
+pub(crate) fn jar() -> Option<&'static Jar> {
+    JAR.get()
+}
+
+fn parse_netscape(contents: &str) -> Vec<Cookie> {
+    let now = Timestamp::now().as_second();
+    let mut cookies = Vec::new();
+    for line in contents.lines() {
+        // curl marks HttpOnly cookies with a prefix on what's otherwise a comment
+        let (http_only, line) = match line.strip_prefix("#HttpOnly_") {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [domain, subdomains, path, secure, expires, name, value] = fields[..] else {
+            debug!("cookies skipped malformed line");
+            continue;
+        };
+        let expires: i64 = expires.parse().unwrap_or(0);
+        if expires != 0 && expires < now {
+            debug!("cookies skipped expired name={name}");
+            continue;
+        }
+        cookies.push(Cookie {
+            domain: domain.trim_start_matches('.').to_ascii_lowercase(),
+            include_subdomains: subdomains.eq_ignore_ascii_case("TRUE") || domain.starts_with('.'),
+            host_only: false,
+            path: path.to_owned(),
+            secure: secure.eq_ignore_ascii_case("TRUE"),
+            http_only,
+            name: name.to_owned(),
+            value: value.to_owned(),
+            expires: (expires != 0).then_some(expires as f64),
+        });
+    }
+    cookies
+}
+
+fn parse_json(contents: &str) -> Result<Vec<Cookie>> {
+    let exported: Vec<Cookie> = serde_json::from_str(contents)?;
+    let now = Timestamp::now().as_second() as f64;
+    let mut cookies = Vec::new();
+    for mut cookie in exported {
+        if cookie.expires.is_some_and(|expires| expires < now) {
+            debug!("cookies skipped expired name={}", cookie.name);
+            continue;
+        }
+        cookie.include_subdomains = !cookie.host_only || cookie.domain.starts_with('.');
+        cookie.domain = cookie.domain.trim_start_matches('.').to_ascii_lowercase();
+        cookies.push(cookie);
+    }
+    Ok(cookies)
+}
+
+impl Jar {
+    /// The `name=value` pairs that should be sent to `url`.
+    pub(crate) fn matching(&self, url: &str) -> Vec<(String, String)> {
+        let Ok(url) = Url::parse(url) else { return Vec::new() };
+        let host = url.host_str().unwrap_or_default().to_ascii_lowercase();
+        let now = Timestamp::now().as_second() as f64;
+        self.cookies
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|c| {
+                host == c.domain
+                    || (c.include_subdomains && host.ends_with(&format!(".{}", c.domain)))
+            })
+            .filter(|c| url.path().starts_with(&c.path))
+            .filter(|c| !c.secure || url.scheme() == "https")
+            .filter(|c| c.expires.is_none_or(|expires| expires >= now))
+            .map(|c| (c.name.clone(), c.value.clone()))
+            .collect()
+    }
+
+    /// Remember a `Set-Cookie` header from a response to `url`. A cookie that's already
+    /// expired removes any with the same name, domain and path.
+    fn store(&self, url: &Url, header: &str) {
+        let Some(cookie) = parse_set_cookie(url, header) else {
+            debug!("cookies ignored malformed Set-Cookie url={url}");
+            return;
+        };
+        let now = Timestamp::now().as_second() as f64;
+        let mut cookies = self.cookies.lock().unwrap();
+        cookies.retain(|c| {
+            (&c.name, &c.domain, &c.path) != (&cookie.name, &cookie.domain, &cookie.path)
+        });
+        if cookie.expires.is_none_or(|expires| expires >= now) {
+            debug!("cookies stored name={} domain={}", cookie.name, cookie.domain);
+            cookies.push(cookie);
+        }
+        self.changed.store(true, Ordering::Relaxed);
+    }
+
+    /// Write the cookies back to the `--cookies` file if CBC changed any, in the format it was
+    /// in, readable only by the current user. Session cookies are kept too, since each run is a
+    /// new session.
+    pub(crate) fn save(&self) -> Result<()> {
+        let Some((path, format)) = &self.file else { return Ok(()) };
+        if !self.changed.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        let cookies = self.cookies.lock().unwrap();
+        let contents = match format {
+            Format::Netscape => to_netscape(&cookies),
+            Format::Json => {
+                let exported: Vec<_> = cookies
+                    .iter()
+                    .map(|c| Cookie { host_only: !c.include_subdomains, ..c.clone() })
+                    .collect();
+                serde_json::to_string_pretty(&exported)?
+            }
+        };
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+            options.mode(0o600);
+            if path.exists() {
+                fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+            }
+        }
+        let mut file = options.open(path).with_context(|| format!("writing {}", path.display()))?;
+        file.write_all(contents.as_bytes())?;
+        debug!("cookies saved count={} file={}", cookies.len(), path.display());
+        Ok(())
+    }
+}
+
+fn to_netscape(cookies: &[Cookie]) -> String {
+    let mut out = String::from("# Netscape HTTP Cookie File\n");
+    for c in cookies {
+        let flag = |set: bool| if set { "TRUE" } else { "FALSE" };
+        let prefix = if c.http_only { "#HttpOnly_" } else { "" };
+        let dot = if c.include_subdomains { "." } else { "" };
+        let expires = c.expires.map_or(0, |expires| expires as i64);
+        out.push_str(&format!(
+            "{prefix}{dot}{}\t{}\t{}\t{}\t{expires}\t{}\t{}\n",
+            c.domain,
+            flag(c.include_subdomains),
+            c.path,
+            flag(c.secure),
+            c.name,
+            c.value
+        ));
+    }
+    out
+}
+
+/// Parse a `Set-Cookie` header for a response from `url`, following RFC 6265's defaults: no
+/// `Domain` means only the host itself, and no `Path` means the request's directory.
+fn parse_set_cookie(url: &Url, header: &str) -> Option<Cookie> {
+    let mut parts = header.split(';');
+    let (name, value) = parts.next()?.split_once('=')?;
+    let name = name.trim();
+    if name.is_empty() {
+        return None;
+    }
+    let host = url.host_str()?.to_ascii_lowercase();
+    let mut cookie = Cookie {
+        domain: host.clone(),
+        include_subdomains: false,
+        host_only: false,
+        path: match url.path().rfind('/') {
+            Some(0) | None => "/".to_owned(),
+            Some(end) => url.path()[..end].to_owned(),
+        },
+        secure: false,
+        http_only: false,
+        name: name.to_owned(),
+        value: value.trim().trim_matches('"').to_owned(),
+        expires: None,
+    };
+    let mut max_age = None;
+    for attribute in parts {
+        let (key, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+        let value = value.trim();
+        match key.trim().to_ascii_lowercase().as_str() {
+            "domain" if !value.is_empty() => {
+                let domain = value.trim_start_matches('.').to_ascii_lowercase();
+                // a site can only set cookies for itself or a parent domain
+                if host != domain && !host.ends_with(&format!(".{domain}")) {
+                    return None;
+                }
+                cookie.domain = domain;
+                cookie.include_subdomains = true;
+            }
+            "path" if value.starts_with('/') => cookie.path = value.to_owned(),
+            "secure" => cookie.secure = true,
+            "httponly" => cookie.http_only = true,
+            "max-age" => max_age = value.parse::<i64>().ok(),
+            "expires" => {
+                let parser = jiff::fmt::rfc2822::DateTimeParser::new();
+                // some servers write dates with dashes, like 21-Oct-2015
+                match parser.parse_timestamp(value.replace('-', " ")) {
+                    Ok(expires) => cookie.expires = Some(expires.as_second() as f64),
+                    Err(_) => debug!("cookies unparsed expires={value}"),
+                }
+            }
+            _ => {}
+        }
+    }
+    // Max-Age wins over Expires
+    if let Some(max_age) = max_age {
+        cookie.expires = Some((Timestamp::now().as_second() + max_age) as f64);
+    }
+    Some(cookie)
+}
+
+impl Middleware for &'static Jar {
+    fn handle(&self, request: Request, next: MiddlewareNext) -> Result<Response, ureq::Error> {
+        let cookies = self.matching(request.url());
+        let request = if cookies.is_empty() {
+            request
+        } else {
+            debug!("cookies sent count={} url={}", cookies.len(), request.url());
+            let mut header: Vec<String> =
+                request.header("Cookie").map(str::to_owned).into_iter().collect();
+            header.extend(cookies.iter().map(|(name, value)| format!("{name}={value}")));
+            request.set("Cookie", &header.join("; "))
+        };
+        let url = Url::parse(request.url()).ok();
+        let result = next.handle(request);
+        let response = match &result {
+            Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+            Err(_) => return result,
+        };
+        if let Some(url) = url {
+            for header in response.all("Set-Cookie") {
+                self.store(&url, header);
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(url: &str) -> Url {
+        Url::parse(url).unwrap()
+    }
+
+    #[test]
+    fn netscape_parsed() {
+        let future = Timestamp::now().as_second() + 3600;
+        let contents = format!(
+            "# Netscape HTTP Cookie File\n\
+            #HttpOnly_.cbc.ca\tTRUE\t/\tTRUE\t0\tsession\tabc\n\
+            www.cbc.ca\tFALSE\t/player\tFALSE\t{future}\tgeo\tCA\n\
+            .Gem.CBC.ca\tFALSE\t/\tFALSE\t0\tdotted\t1\n\
+            cbc.ca\tTRUE\t/\tFALSE\t1\texpired\tx\n\
+            \n\
+            not a cookie line\n"
+        );
+        let cookies = parse_netscape(&contents);
+        let names: Vec<_> = cookies.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["session", "geo", "dotted"]);
+        let [session, geo, dotted] = &cookies[..] else { unreachable!() };
+        assert!(session.http_only && session.secure && session.include_subdomains);
+        assert_eq!((session.domain.as_str(), session.expires), ("cbc.ca", None));
+        assert!(!geo.http_only && !geo.include_subdomains);
+        assert_eq!((geo.path.as_str(), geo.expires), ("/player", Some(future as f64)));
+        // a leading dot means subdomains too, whatever the flag says
+        assert_eq!(dotted.domain, "gem.cbc.ca");
+        assert!(dotted.include_subdomains);
+    }
+
+    #[test]
+    fn matching_cookies() {
+        let contents = "#HttpOnly_.cbc.ca\tTRUE\t/\tTRUE\t0\tsession\tabc\n\
+            www.cbc.ca\tFALSE\t/player\tFALSE\t0\tgeo\tCA\n";
+        let jar = Jar { cookies: Mutex::new(parse_netscape(contents)), ..Jar::default() };
+        let names = |url: &str| -> Vec<String> {
+            jar.matching(url).into_iter().map(|(name, _)| name).collect()
+        };
+        assert_eq!(names("https://www.cbc.ca/player/play/video/1.2"), ["session", "geo"]);
+        assert_eq!(names("https://gem.cbc.ca/"), ["session"]);
+        assert_eq!(names("http://www.cbc.ca/player/"), ["geo"]);
+        assert!(names("https://notcbc.ca/").is_empty());
+    }
+
+    #[test]
+    fn set_cookie_defaults() {
+        let page = url("https://www.cbc.ca/player/play/video/1.7276395");
+        let cookie = parse_set_cookie(&page, "geo=CA").unwrap();
+        assert_eq!((cookie.name.as_str(), cookie.value.as_str()), ("geo", "CA"));
+        assert_eq!(cookie.domain, "www.cbc.ca");
+        assert!(!cookie.include_subdomains && !cookie.secure && !cookie.http_only);
+        // RFC 6265's default path: the request path up to its last slash
+        assert_eq!(cookie.path, "/player/play/video");
+        assert_eq!(cookie.expires, None);
+        assert_eq!(parse_set_cookie(&url("https://www.cbc.ca/player"), "a=1").unwrap().path, "/");
+        assert_eq!(parse_set_cookie(&url("https://www.cbc.ca"), "a=1").unwrap().path, "/");
+
+        let cookie = parse_set_cookie(&page, "a=\"1\"; Path=/; Secure; HttpOnly").unwrap();
+        assert_eq!((cookie.value.as_str(), cookie.path.as_str()), ("1", "/"));
+        assert!(cookie.secure && cookie.http_only);
+        // not a path, so the default stays
+        assert_eq!(parse_set_cookie(&page, "a=1; Path=x").unwrap().path, "/player/play/video");
+
+        assert_eq!(parse_set_cookie(&page, "=1"), None);
+        assert_eq!(parse_set_cookie(&page, "novalue"), None);
+    }
+
+    #[test]
+    fn set_cookie_expiry() {
+        let page = url("https://www.cbc.ca/");
+        let expires = "Expires=Wed, 21 Oct 2015 07:28:00 GMT";
+        let cookie = parse_set_cookie(&page, &format!("a=1; {expires}")).unwrap();
+        assert_eq!(cookie.expires, Some(1445412480.0));
+        let cookie = parse_set_cookie(&page, "a=1; Expires=Wed, 21-Oct-2015 07:28:00 GMT").unwrap();
+        assert_eq!(cookie.expires, Some(1445412480.0));
+        // Max-Age wins, whichever comes first
+        for header in
+            [format!("a=1; {expires}; Max-Age=3600"), format!("a=1; Max-Age=3600; {expires}")]
+        {
+            let before = Timestamp::now().as_second() + 3600;
+            let expires = parse_set_cookie(&page, &header).unwrap().expires.unwrap() as i64;
+            assert!((before..before + 5).contains(&expires), "{header}: {expires}");
+        }
+    }
+
+    #[test]
+    fn set_cookie_domain() {
+        let page = url("https://www.cbc.ca/player/");
+        let cookie = parse_set_cookie(&page, "a=1; Domain=.CBC.ca").unwrap();
+        assert_eq!(cookie.domain, "cbc.ca");
+        assert!(cookie.include_subdomains);
+        let cookie = parse_set_cookie(&page, "a=1; Domain=www.cbc.ca").unwrap();
+        assert_eq!(cookie.domain, "www.cbc.ca");
+        assert!(cookie.include_subdomains);
+        // only the host itself or a parent of it
+        assert_eq!(parse_set_cookie(&page, "a=1; Domain=example.com"), None);
+        assert_eq!(parse_set_cookie(&page, "a=1; Domain=evilcbc.ca"), None);
+        assert_eq!(parse_set_cookie(&page, "a=1; Domain=gem.cbc.ca"), None);
+    }
+
+    #[test]
+    fn netscape_round_trip() {
+        let contents = "# Netscape HTTP Cookie File\n\
+            #HttpOnly_.cbc.ca\tTRUE\t/\tTRUE\t0\tsession\tabc\n\
+            www.cbc.ca\tFALSE\t/player\tFALSE\t4102444800\tgeo\tCA\n";
+        let cookies = parse_netscape(contents);
+        assert_eq!(to_netscape(&cookies), contents);
+        assert_eq!(parse_netscape(&to_netscape(&cookies)), cookies);
+    }
+}
@@ -0,0 +1,117 @@
+use serde::Deserialize;
+use ureq::Agent;
+use url::Url;
+
+use crate::api::Asset;
+use crate::debug::{self, debug};
+use crate::error::CbcError;
+
+/// Where Google's dynamic ad insertion (DAI) API creates stream sessions.
+const API_BASE: &str = "https://dai.google.com";
+
+/// A `platform-dai` asset, which names a stream in Google's DAI service rather than giving a
+/// URL that can be fetched directly: a stream session has to be created for it first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Dai {
+    /// A replay, identified by the content source (CBC's CMS) and its video ID there
+    OnDemand { content_source_id: String, video_id: String },
+    /// A live stream, identified by its asset key
+    Live { asset_key: String },
+}
+
+/// What creating a stream session returns. There's more in it (subtitles, ad verification and
+/// polling URLs) that we don't need to play the stream.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct StreamSession {
+    stream_id: Option<String>,
+    /// The HLS master playlist, with ads stitched into the video
+    stream_manifest: String,
+}
+
+impl Dai {
+    /// Read the IDs out of a `platform-dai` asset. The key is a URL with them in its path
+    /// (`/ondemand/hls/content/2525/vid/ABC123/streams`, `/ssai/event/KEY/streams`) or query
+    /// (`cmsid` and `vid`); failing that, the asset's options may have them.
+    pub(crate) fn from_asset(asset: &Asset) -> Result<Self, CbcError> {
+        if let Some(dai) = Self::from_key(&asset.key) {
+            return Ok(dai);
+        }
+        let option = |name: &str| {
+            let value = asset.options.as_ref()?.get(name)?;
+            match value {
+                serde_json::Value::String(s) if !s.is_empty() => Some(s.clone()),
+                serde_json::Value::Number(n) => Some(n.to_string()),
+                _ => None,
+            }
+        };
+        match (option("contentSourceId"), option("videoId"), option("assetKey")) {
+            (Some(content_source_id), Some(video_id), _) => {
+                Ok(Self::OnDemand { content_source_id, video_id })
+            }
+            (_, _, Some(asset_key)) => Ok(Self::Live { asset_key }),
+            _ => Err(CbcError::ApiChanged {
+                context: format!("couldn't find the DAI stream IDs in {}", asset.key),
+            }),
+        }
+    }
+
+    fn from_key(key: &str) -> Option<Self> {
+        let url = Url::parse(key).ok()?;
+        let segments: Vec<&str> = url.path_segments()?.filter(|s| !s.is_empty()).collect();
+        let after = |name: &str| {
+            let at = segments.iter().position(|s| *s == name)?;
+            segments.get(at + 1).map(|s| (*s).to_owned())
+        };
+        if let (Some(content_source_id), Some(video_id)) = (after("content"), after("vid")) {
+            return Some(Self::OnDemand { content_source_id, video_id });
+        }
+        if let Some(asset_key) = after("event") {
+            return Some(Self::Live { asset_key });
+        }
+        let query = |name: &str| {
+            url.query_pairs().find(|(n, v)| n == name && !v.is_empty()).map(|(_, v)| v.into_owned())
+        };
+        match (query("cmsid"), query("vid")) {
+            (Some(content_source_id), Some(video_id)) => {
+                Some(Self::OnDemand { content_source_id, video_id })
+            }
+            _ => None,
+        }
+    }
+
+    /// Where to POST to create a stream session.
+    fn streams_url(&self) -> String {
+        match self {
+            Self::OnDemand { content_source_id, video_id } => {
+                format!(
+                    "{API_BASE}/ondemand/hls/content/{content_source_id}/vid/{video_id}/streams"
+                )
+            }
+            Self::Live { asset_key } => format!("{API_BASE}/ssai/event/{asset_key}/streams"),
+        }
+    }
+
+    /// Create a stream session and return its master playlist URL. Each session is good for
+    /// one viewing, so this is repeated when restarting a recording.
+    pub(crate) fn master_url(&self, agent: &Agent) -> Result<String, CbcError> {
+        let url = self.streams_url();
+        debug!("request method=POST url={url}");
+        let resp = match agent.post(&url).send_form(&[]) {
+            Ok(resp) => resp,
+            Err(ureq::Error::Status(404, _)) => {
+                let what = "the DAI stream isn't published yet (HTTP 404)".to_owned();
+                return Err(CbcError::NotFound { what });
+            }
+            Err(ureq::Error::Status(403, _)) => {
+                return Err(CbcError::GeoBlocked { reason: "DAI returned HTTP 403".to_owned() });
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let body = resp.into_string()?;
+        debug::dump("dai-stream.json", &body);
+        let session: StreamSession = serde_json::from_str(&body)
+            .map_err(|e| CbcError::parse("the DAI stream session", e))?;
+        debug!("dai session={}", session.stream_id.as_deref().unwrap_or("?"));
+        Ok(session.stream_manifest)
+    }
+}
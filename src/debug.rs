@@ -0,0 +1,205 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use anyhow::{Context, Result};
+use lazy_regex::regex;
+use once_cell::sync::OnceCell;
+use ureq::{Middleware, MiddlewareNext, Request, Response};
+use url::Url;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static DUMP_DIR: OnceCell<PathBuf> = OnceCell::new();
+static STATE_DUMP: OnceCell<StateDump> = OnceCell::new();
+
+/// The payloads `--dump-state` saves, by their `--debug-dump` names: what's most useful to see
+/// when CBC changes something.
+const STATE_FILES: &[&str] =
+    &["player-page.html", "initial-state.json", "stream.json", "dai-stream.json", "master.m3u8"];
+
+/// Where `--dump-state` saves payloads, and what it's saved so far.
+struct StateDump {
+    dir: PathBuf,
+    /// When this run started, to keep its files apart from other runs'
+    prefix: String,
+    saved: Mutex<Vec<PathBuf>>,
+}
+
+/// Log a line to stderr if `--debug` was passed. Lines are `key=value` pairs so they're easy
+/// to grep through in bug reports.
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if $crate::debug::enabled() {
+            eprintln!("[debug] {}", format_args!($($arg)*));
+        }
+    };
+}
+pub(crate) use debug;
+
+/// Turn on debug logging, the library's included, and/or dumping of raw payloads to `dump_dir`.
+pub(crate) fn init(enabled: bool, dump_dir: Option<PathBuf>) -> Result<()> {
+    ENABLED.store(enabled, Ordering::Relaxed);
+    if enabled {
+        cbc_sl::set_debug_logger(|line| eprintln!("[debug] {line}"));
+    }
+    if let Some(dir) = dump_dir {
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("creating dump directory {}", dir.display()))?;
+        let _ = DUMP_DIR.set(dir);
+    }
+    Ok(())
+}
+
+pub(crate) fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Turn on `--dump-state`, saving the main payloads to `dir` as they're received.
+pub(crate) fn init_state_dump(dir: PathBuf) -> Result<()> {
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("creating state dump directory {}", dir.display()))?;
+    let prefix = jiff::Zoned::now().strftime("%Y%m%d-%H%M%S").to_string();
+    let _ = STATE_DUMP.set(StateDump { dir, prefix, saved: Mutex::default() });
+    Ok(())
+}
+
+/// The files `--dump-state` has saved so far.
+pub(crate) fn state_files() -> Vec<PathBuf> {
+    STATE_DUMP.get().map_or_else(Vec::new, |state| state.saved.lock().unwrap().clone())
+}
+
+/// Write `contents` to `name` inside the `--debug-dump` directory, if one was given, and with
+/// `--dump-state`, save it there too if it's one of the payloads that's for. Failing to write a
+/// dump is logged but never fatal.
+pub(crate) fn dump(name: &str, contents: &str) {
+    if let Some(state) = STATE_DUMP.get().filter(|_| STATE_FILES.contains(&name)) {
+        let path = state.dir.join(format!("{}-{name}", state.prefix));
+        if write_dump(&path, &redact(name, contents)) {
+            state.saved.lock().unwrap().push(path);
+        }
+    }
+    let Some(dir) = DUMP_DIR.get() else { return };
+    write_dump(&dir.join(name), contents);
+}
+
+fn write_dump(path: &Path, contents: &str) -> bool {
+    match fs::write(path, contents) {
+        Ok(()) => {
+            debug!("dumped file={} bytes={}", path.display(), contents.len());
+            true
+        }
+        Err(e) => {
+            eprintln!("failed to write {}: {e}", path.display());
+            false
+        }
+    }
+}
+
+/// `contents` with anything that looks like an access token replaced, so it can be attached
+/// to a public issue: signed URL parameters (Akamai's `hdnea` and the like) and token fields
+/// in JSON. The player state is also pretty-printed, since it's one very long line on the page.
+fn redact(name: &str, contents: &str) -> String {
+    let param = regex!(
+        r#"(?i)([?&;](?:[a-z_]*token|sig|signature|hdnea|hdntl|hdnts|auth|policy|key-pair-id|hmac|acl)=)[^&"'\s<>]*"#
+    );
+    let field = regex!(r#"(?i)("[a-z_]*(?:token|jwt|secret)"\s*:\s*")[^"]*""#);
+    let redacted = param.replace_all(contents, "${1}REDACTED");
+    let redacted = field.replace_all(&redacted, "${1}REDACTED\"");
+    if name == "initial-state.json" {
+        if let Ok(value) = serde_json::from_str::<serde_json::Value>(&redacted) {
+            if let Ok(pretty) = serde_json::to_string_pretty(&value) {
+                return pretty;
+            }
+        }
+    }
+    redacted.into_owned()
+}
+
+/// With `--http-debug`, logs every request and response to stderr: method, URL, the headers we
+/// set, status, response headers and how long it took. Query string values are hidden unless
+/// `full` is set, since medianet and playlist URLs carry access tokens. Cookies and the account's
+/// claims token are always hidden.
+pub(crate) struct HttpLogger {
+    pub(crate) full: bool,
+}
+
+impl HttpLogger {
+    fn redact_url(&self, url: &str) -> String {
+        let Ok(mut parsed) = Url::parse(url) else { return url.to_owned() };
+        if self.full || parsed.query().is_none() {
+            return url.to_owned();
+        }
+        let names: Vec<String> = parsed.query_pairs().map(|(name, _)| name.into_owned()).collect();
+        parsed.query_pairs_mut().clear().extend_pairs(names.iter().map(|name| (name, "REDACTED")));
+        parsed.to_string()
+    }
+
+    fn redact_header(&self, name: &str, value: &str) -> String {
+        let secret = ["Cookie", "Set-Cookie", "x-claims-token"]
+            .iter()
+            .any(|secret| name.eq_ignore_ascii_case(secret));
+        if secret || (!self.full && name.eq_ignore_ascii_case("Authorization")) {
+            "REDACTED".to_owned()
+        } else {
+            value.to_owned()
+        }
+    }
+}
+
+impl Middleware for HttpLogger {
+    fn handle(&self, request: Request, next: MiddlewareNext) -> Result<Response, ureq::Error> {
+        eprintln!("[http] > {} {}", request.method(), self.redact_url(request.url()));
+        for name in request.header_names() {
+            let value = request.header(&name).unwrap_or_default();
+            eprintln!("[http] > {name}: {}", self.redact_header(&name, value));
+        }
+        let start = Instant::now();
+        let result = next.handle(request);
+        let elapsed = start.elapsed().as_millis();
+        let response = match &result {
+            Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+            Err(e) => {
+                eprintln!("[http] < failed after {elapsed}ms: {e}");
+                return result;
+            }
+        };
+        eprintln!(
+            "[http] < {} {} in {elapsed}ms from {}",
+            response.status(),
+            response.status_text(),
+            self.redact_url(response.get_url())
+        );
+        for name in response.headers_names() {
+            for value in response.all(&name) {
+                eprintln!("[http] < {name}: {}", self.redact_header(&name, value));
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn claims_token_always_hidden() {
+        for full in [false, true] {
+            let logger = HttpLogger { full };
+            assert_eq!(logger.redact_header("x-claims-token", "eyJhbGciOi"), "REDACTED");
+            assert_eq!(logger.redact_header("X-Claims-Token", "eyJhbGciOi"), "REDACTED");
+            assert_eq!(logger.redact_header("Cookie", "session=1"), "REDACTED");
+        }
+        assert_eq!(
+            HttpLogger { full: false }.redact_header("Authorization", "Bearer x"),
+            "REDACTED"
+        );
+        assert_eq!(
+            HttpLogger { full: true }.redact_header("Authorization", "Bearer x"),
+            "Bearer x"
+        );
+        assert_eq!(HttpLogger { full: false }.redact_header("Accept", "*/*"), "*/*");
+    }
+}
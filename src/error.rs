@@ -0,0 +1,170 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::exit::{self, Code};
+
+/// What [CbcError::GeoBlocked] says is probably wrong, for the binary to pick out in color.
+pub const GEO_BLOCKED: &str = "your IP is geo-blocked";
+
+/// What went wrong getting from a video to a stream, for callers that need to tell failures
+/// apart rather than just print them. The `cbc-sl` binary turns these into exit codes with
+/// [CbcError::code].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum CbcError {
+    /// CBC refused to hand over the stream, almost always because of geo-blocking
+    GeoBlocked { reason: String },
+    /// The video, its stream or a playable variant isn't there, or isn't published yet
+    NotFound { what: String },
+    /// The video needs a CBC account or is premium content
+    RequiresAccount,
+    /// medianet refused the stream for some other reason, like the video having been taken
+    /// down. `code` is medianet's `errorCode`
+    Medianet { code: i64, status: u16, message: String },
+    /// CBC sent something shaped differently from what we expect; it has probably changed its
+    /// site or API
+    ApiChanged { context: String },
+    /// Couldn't reach CBC, or it answered with an error status
+    Network(Box<ureq::Error>),
+    /// Something CBC sent couldn't be parsed
+    Parse { what: &'static str, source: Box<dyn Error + Send + Sync> },
+    /// streamlink ran but failed. `code` is `None` if it was killed by a signal, in which case
+    /// `signal` says which on Unix. `reason` is the last error it printed, if any
+    StreamlinkFailed { code: Option<i32>, signal: Option<i32>, reason: Option<String> },
+    /// streamlink hadn't started playing after `seconds`, so it was killed
+    StreamlinkStalled { seconds: u64 },
+}
+
+impl CbcError {
+    /// A [CbcError::Parse] for `what`, e.g. `the master playlist`.
+    pub fn parse(what: &'static str, source: impl Into<Box<dyn Error + Send + Sync>>) -> Self {
+        Self::Parse { what, source: source.into() }
+    }
+
+    /// The exit code this should produce, if it's one scripts can tell apart.
+    pub fn code(&self) -> Option<Code> {
+        match self {
+            Self::GeoBlocked { .. } => Some(Code::Geoblocked),
+            Self::NotFound { .. } | Self::StreamlinkStalled { .. } => Some(Code::NoStreams),
+            Self::Network(e) => exit::code_for_ureq(e),
+            Self::RequiresAccount
+            | Self::Medianet { .. }
+            | Self::ApiChanged { .. }
+            | Self::Parse { .. }
+            | Self::StreamlinkFailed { .. } => None,
+        }
+    }
+
+    /// Whether this means the stream just isn't there yet, as with a live event that hasn't
+    /// started: a 404, or a playlist with nothing in it.
+    pub fn is_not_ready(&self) -> bool {
+        match self {
+            Self::NotFound { .. } => true,
+            Self::Network(e) => matches!(**e, ureq::Error::Status(404, _)),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for CbcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::GeoBlocked { reason } => write!(
+                f,
+                "CBC wouldn't give us the stream ({reason}); this probably means {GEO_BLOCKED}"
+            ),
+            Self::NotFound { what } => f.write_str(what),
+            Self::RequiresAccount => f.write_str(
+                "this video requires signing in to a CBC account (see `cbc-sl login`), or is \
+                premium content, which cbc-sl can't play",
+            ),
+            Self::Medianet { code, status, message } => {
+                write!(f, "CBC wouldn't give us the stream: medianet error {code} (HTTP {status}): {message}")
+            }
+            Self::ApiChanged { context } => {
+                write!(f, "{context}; CBC may have changed its site, and cbc-sl may need an update")
+            }
+            Self::Network(e) => match **e {
+                ureq::Error::Status(429, _) => f.write_str(
+                    "CBC is rate-limiting requests; wait a minute before trying again, or raise \
+                    --max-retry-wait",
+                ),
+                ureq::Error::Status(..) => f.write_str("the server returned an error"),
+                ureq::Error::Transport(_) => f.write_str("couldn't connect"),
+            },
+            Self::Parse { what, .. } => write!(f, "couldn't parse {what}"),
+            Self::StreamlinkFailed { code, signal, reason } => {
+                match (code, signal) {
+                    (Some(code), _) => write!(f, "streamlink exit code: {code}")?,
+                    (None, Some(signal)) => match signal_name(*signal) {
+                        Some(name) => write!(f, "streamlink was killed by {name}")?,
+                        None => write!(f, "streamlink was killed by signal {signal}")?,
+                    },
+                    (None, None) => f.write_str("streamlink exited unexpectedly")?,
+                }
+                match reason {
+                    Some(reason) => write!(f, " ({reason})"),
+                    None => Ok(()),
+                }
+            }
+            Self::StreamlinkStalled { seconds } => write!(
+                f,
+                "streamlink hadn't started playing after {seconds}s, so it was stopped; the stream \
+                may be dead"
+            ),
+        }
+    }
+}
+
+/// The name of a common signal, like `SIGTERM`.
+fn signal_name(signal: i32) -> Option<&'static str> {
+    #[cfg(unix)]
+    {
+        use libc::{SIGABRT, SIGHUP, SIGINT, SIGKILL, SIGPIPE, SIGQUIT, SIGSEGV, SIGTERM};
+        Some(match signal {
+            SIGHUP => "SIGHUP",
+            SIGINT => "SIGINT",
+            SIGQUIT => "SIGQUIT",
+            SIGABRT => "SIGABRT",
+            SIGKILL => "SIGKILL",
+            SIGSEGV => "SIGSEGV",
+            SIGPIPE => "SIGPIPE",
+            SIGTERM => "SIGTERM",
+            _ => return None,
+        })
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = signal;
+        None
+    }
+}
+
+impl Error for CbcError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Network(e) => Some(&**e),
+            Self::Parse { source, .. } => Some(&**source),
+            _ => None,
+        }
+    }
+}
+
+impl From<Box<ureq::Error>> for CbcError {
+    fn from(e: Box<ureq::Error>) -> Self {
+        Self::Network(e)
+    }
+}
+
+impl From<ureq::Error> for CbcError {
+    fn from(e: ureq::Error) -> Self {
+        Self::Network(Box::new(e))
+    }
+}
+
+impl From<std::io::Error> for CbcError {
+    /// Reading a response body failed partway through.
+    fn from(e: std::io::Error) -> Self {
+        Self::Network(Box::new(e.into()))
+    }
+}
@@ -0,0 +1,137 @@
+use std::fmt;
+use std::io;
+
+use crate::error::CbcError;
+
+/// Exit codes for failures that scripts may want to tell apart. Anything else exits with 1.
+/// Keep the table in the README in sync.
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub enum Code {
+    /// CBC refused us, almost always because of geo-blocking
+    Geoblocked = 2,
+    /// The video or listing had nothing playable in it
+    NoStreams = 3,
+    /// Couldn't reach CBC, or the connection timed out
+    Network = 4,
+    /// The streamlink program couldn't be found
+    StreamlinkMissing = 5,
+    /// Bad arguments or an ID/URL that couldn't be used
+    InvalidInput = 6,
+    /// Stopped by SIGHUP while streamlink was running, as shells report for it (Unix)
+    Hangup = 129,
+    /// Stopped with Ctrl-C while streamlink was running, as shells report for SIGINT
+    Interrupted = 130,
+    /// Stopped by SIGTERM while streamlink was running, as shells report for it (Unix)
+    Terminated = 143,
+}
+
+/// An error message tagged with the exit code it should produce. It can be the error itself
+/// (`bail!(Failure::new(...))`) or context on another error.
+#[derive(Debug)]
+pub struct Failure {
+    code: Code,
+    message: String,
+}
+
+impl Failure {
+    pub fn new(code: Code, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+
+    pub fn code(&self) -> Code {
+        self.code
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Failure {}
+
+/// Work out the exit code for an error, preferring an explicit [Failure] tag, then a
+/// [CbcError], and otherwise looking at what went wrong underneath.
+pub fn code_for(err: &anyhow::Error) -> u8 {
+    if let Some(failure) = err.downcast_ref::<Failure>() {
+        return failure.code as u8;
+    }
+    if let Some(e) = err.downcast_ref::<CbcError>() {
+        return e.code().map_or(1, |code| code as u8);
+    }
+    for cause in err.chain() {
+        if let Some(e) = cause.downcast_ref::<ureq::Error>() {
+            return code_for_ureq(e).map_or(1, |code| code as u8);
+        }
+        if let Some(e) = cause.downcast_ref::<io::Error>() {
+            if e.kind() == io::ErrorKind::TimedOut {
+                return Code::Network as u8;
+            }
+        }
+    }
+    1
+}
+
+/// The exit code for a failed request, if it's one scripts can tell apart.
+pub(crate) fn code_for_ureq(e: &ureq::Error) -> Option<Code> {
+    match e {
+        ureq::Error::Status(403 | 451, _) => Some(Code::Geoblocked),
+        ureq::Error::Status(..) => None,
+        ureq::Error::Transport(_) => Some(Code::Network),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Context;
+
+    use super::*;
+
+    fn status(code: u16) -> ureq::Error {
+        ureq::Error::Status(code, ureq::Response::new(code, "Error", "").unwrap())
+    }
+
+    fn transport() -> ureq::Error {
+        io::Error::new(io::ErrorKind::ConnectionRefused, "refused").into()
+    }
+
+    #[test]
+    fn cbc_error_codes() {
+        let cases: Vec<(CbcError, u8)> = vec![
+            (CbcError::GeoBlocked { reason: "HTTP 403".to_owned() }, 2),
+            (CbcError::NotFound { what: "no streams found".to_owned() }, 3),
+            (CbcError::StreamlinkStalled { seconds: 30 }, 3),
+            (CbcError::Network(Box::new(status(403))), 2),
+            (CbcError::Network(Box::new(status(451))), 2),
+            (CbcError::Network(Box::new(status(500))), 1),
+            (CbcError::Network(Box::new(transport())), 4),
+            (CbcError::RequiresAccount, 1),
+            (CbcError::Medianet { code: 12, status: 200, message: "gone".to_owned() }, 1),
+            (CbcError::ApiChanged { context: "no player state".to_owned() }, 1),
+            (CbcError::parse("the master playlist", "bad tag"), 1),
+            (CbcError::StreamlinkFailed { code: Some(1), signal: None, reason: None }, 1),
+        ];
+        for (error, code) in cases {
+            let description = format!("{error:?}");
+            assert_eq!(code_for(&error.into()), code, "{description}");
+        }
+    }
+
+    #[test]
+    fn other_error_codes() {
+        let failure = Failure::new(Code::InvalidInput, "bad URL");
+        assert_eq!(code_for(&failure.into()), 6);
+        // a Failure as context wins over what it wraps
+        let wrapped = Err::<(), _>(status(403)).context(Failure::new(Code::NoStreams, "no"));
+        assert_eq!(code_for(&wrapped.unwrap_err()), 3);
+        // underneath other context
+        let wrapped = Err::<(), _>(status(451)).context("fetching the stream");
+        assert_eq!(code_for(&wrapped.unwrap_err()), 2);
+        let wrapped = Err::<(), _>(transport()).context("fetching the stream");
+        assert_eq!(code_for(&wrapped.unwrap_err()), 4);
+        let timeout = io::Error::new(io::ErrorKind::TimedOut, "timed out");
+        assert_eq!(code_for(&anyhow::Error::new(timeout).context("reading")), 4);
+        assert_eq!(code_for(&anyhow::anyhow!("something else")), 1);
+    }
+}
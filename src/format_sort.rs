@@ -0,0 +1,241 @@
+use std::cmp::Ordering;
+
+use hls_m3u8::tags::VariantStream;
+
+use crate::VariantStreamExt;
+
+/// Video codecs from most to least preferred when sorting by plain `codec`, as yt-dlp does.
+const CODECS: &[(&str, &[&str])] = &[
+    ("av1", &["av01"]),
+    ("vp9", &["vp09", "vp9"]),
+    ("h265", &["hvc1", "hev1"]),
+    ("h264", &["avc1", "avc3"]),
+];
+
+/// A `--format-sort` order, like yt-dlp's `-S`: keys in priority order, each preferring higher
+/// values unless prefixed with `+`, or values closest to (but not over) a `:LIMIT`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct FormatSort(Vec<SortKey>);
+
+#[derive(Debug, Clone, PartialEq)]
+struct SortKey {
+    field: Field,
+    ascending: bool,
+    limit: Option<Limit>,
+}
+
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+enum Field {
+    /// Height in pixels
+    Res,
+    /// Peak bandwidth in bits per second
+    Br,
+    /// Frame rate
+    Fps,
+    /// Video codec
+    Codec,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Limit {
+    Number(f64),
+    /// Index into [CODECS]
+    Codec(usize),
+}
+
+impl FormatSort {
+    /// Parse a comma-separated list of keys such as `res:1080,br,codec:h264`.
+    pub(crate) fn parse(input: &str) -> Result<Self, String> {
+        let mut keys = Vec::new();
+        for key in input.split(',').map(str::trim).filter(|k| !k.is_empty()) {
+            let (ascending, key) = match key.strip_prefix('+') {
+                Some(key) => (true, key),
+                None => (false, key),
+            };
+            let (name, limit) = match key.split_once(':') {
+                Some((name, limit)) => (name, Some(limit)),
+                None => (key, None),
+            };
+            let field = match name {
+                "res" => Field::Res,
+                "br" | "tbr" => Field::Br,
+                "fps" => Field::Fps,
+                "codec" | "vcodec" => Field::Codec,
+                _ => return Err(format!("unknown sort key '{name}'; use res, br, fps or codec")),
+            };
+            let limit = match (field, limit) {
+                (_, None) => None,
+                (Field::Codec, Some(codec)) => {
+                    let index = CODECS.iter().position(|(name, _)| *name == codec);
+                    let names: Vec<_> = CODECS.iter().map(|(name, _)| *name).collect();
+                    let index = index.ok_or_else(|| {
+                        format!("unknown codec '{codec}'; use one of {}", names.join(", "))
+                    })?;
+                    Some(Limit::Codec(index))
+                }
+                (_, Some(limit)) => {
+                    let limit = limit.trim_end_matches(['p', 'k', 'K']);
+                    let number: f64 =
+                        limit.parse().map_err(|_| format!("'{key}' needs a number after ':'"))?;
+                    // yt-dlp's br is in kbit/s, and that's what people will bring with them
+                    let number = if field == Field::Br { number * 1000.0 } else { number };
+                    Some(Limit::Number(number))
+                }
+            };
+            keys.push(SortKey { field, ascending, limit });
+        }
+        if keys.is_empty() {
+            return Err("no sort keys given".to_owned());
+        }
+        Ok(Self(keys))
+    }
+
+    /// Order two variants, most preferred first. Ties fall back to the highest bandwidth.
+    pub(crate) fn compare(&self, a: &VariantStream<'_>, b: &VariantStream<'_>) -> Ordering {
+        self.0
+            .iter()
+            .map(|key| key.compare(a, b))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| b.bandwidth().cmp(&a.bandwidth()))
+    }
+}
+
+impl SortKey {
+    fn compare(&self, a: &VariantStream<'_>, b: &VariantStream<'_>) -> Ordering {
+        if let Some(Limit::Codec(wanted)) = self.limit {
+            // the chosen codec first, then the rest in the usual order
+            let rank = |v: &VariantStream<'_>| {
+                let codec = codec_rank(v);
+                (codec != Some(wanted), codec.unwrap_or(usize::MAX))
+            };
+            return rank(a).cmp(&rank(b));
+        }
+        let (a, b) = (self.value(a), self.value(b));
+        // variants missing the value always go last
+        let (a, b) = match (a, b) {
+            (Some(a), Some(b)) => (a, b),
+            (a, b) => return a.is_none().cmp(&b.is_none()),
+        };
+        let ordering = match self.limit {
+            Some(Limit::Number(limit)) => {
+                // everything within the limit beats everything over it; within it, closer is
+                // better, and over it, smaller is better
+                match (a <= limit, b <= limit) {
+                    (true, false) => Ordering::Less,
+                    (false, true) => Ordering::Greater,
+                    (true, true) => b.total_cmp(&a),
+                    (false, false) => a.total_cmp(&b),
+                }
+            }
+            _ => b.total_cmp(&a),
+        };
+        if self.ascending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+
+    /// The variant's value for this key, as a number where bigger is better.
+    fn value(&self, v: &VariantStream<'_>) -> Option<f64> {
+        match self.field {
+            Field::Res => v.resolution().map(|(_, height)| f64::from(height)),
+            Field::Br => Some(v.bandwidth() as f64),
+            Field::Fps => v.frame_rate().map(f64::from),
+            Field::Codec => codec_rank(v).map(|rank| -(rank as f64)),
+        }
+    }
+}
+
+/// Where the variant's video codec comes in [CODECS], if it's one we know.
+fn codec_rank(v: &VariantStream<'_>) -> Option<usize> {
+    let codecs = v.codecs()?;
+    codecs.split(',').map(str::trim).find_map(|codec| {
+        CODECS.iter().position(|(_, prefixes)| prefixes.iter().any(|p| codec.starts_with(p)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use hls_m3u8::MasterPlaylist;
+
+    use super::*;
+
+    const LADDER: &str = "#EXTM3U
+#EXT-X-STREAM-INF:BANDWIDTH=5000000,RESOLUTION=1920x1080,CODECS=\"avc1.640028,mp4a.40.2\"
+1080.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=3000000,RESOLUTION=1280x720,CODECS=\"avc1.64001f,mp4a.40.2\"
+720.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=1500000,RESOLUTION=960x540,CODECS=\"avc1.64001f,mp4a.40.2\"
+540.m3u8
+";
+
+    const MIXED_CODECS: &str = "#EXTM3U
+#EXT-X-STREAM-INF:BANDWIDTH=1000000
+unknown.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=3000000,RESOLUTION=1280x720,CODECS=\"avc1.64001f,mp4a.40.2\"
+h264.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=2500000,RESOLUTION=1280x720,CODECS=\"hvc1.1.6.L93.B0,mp4a.40.2\"
+h265.m3u8
+";
+
+    /// The playlist's URIs, most preferred first.
+    fn sorted(keys: &str, playlist: &str) -> Vec<String> {
+        let sort = FormatSort::parse(keys).unwrap();
+        let mut variants = MasterPlaylist::try_from(playlist).unwrap().variant_streams;
+        variants.sort_by(|a, b| sort.compare(a, b));
+        let uri = |v: &VariantStream| match v {
+            VariantStream::ExtXStreamInf { uri, .. } | VariantStream::ExtXIFrame { uri, .. } => {
+                uri.to_string()
+            }
+        };
+        variants.iter().map(uri).collect()
+    }
+
+    #[test]
+    fn res_limit_prefers_closest_under_it() {
+        assert_eq!(sorted("res:720", LADDER), ["720.m3u8", "540.m3u8", "1080.m3u8"]);
+        assert_eq!(sorted("res:720p", LADDER), ["720.m3u8", "540.m3u8", "1080.m3u8"]);
+        // nothing under the limit: the smallest over it
+        assert_eq!(sorted("res:360", LADDER), ["540.m3u8", "720.m3u8", "1080.m3u8"]);
+        assert_eq!(sorted("res", LADDER), ["1080.m3u8", "720.m3u8", "540.m3u8"]);
+    }
+
+    #[test]
+    fn ascending_bandwidth() {
+        assert_eq!(sorted("+br", LADDER), ["540.m3u8", "720.m3u8", "1080.m3u8"]);
+        assert_eq!(sorted("br", LADDER), ["1080.m3u8", "720.m3u8", "540.m3u8"]);
+    }
+
+    #[test]
+    fn br_limit_in_kbits() {
+        assert_eq!(sorted("br:3500", LADDER), ["720.m3u8", "540.m3u8", "1080.m3u8"]);
+        assert_eq!(sorted("br:3000k", LADDER), ["720.m3u8", "540.m3u8", "1080.m3u8"]);
+    }
+
+    #[test]
+    fn codec_limit_puts_it_first() {
+        assert_eq!(sorted("codec:h264", MIXED_CODECS), ["h264.m3u8", "h265.m3u8", "unknown.m3u8"]);
+        assert_eq!(sorted("codec", MIXED_CODECS), ["h265.m3u8", "h264.m3u8", "unknown.m3u8"]);
+    }
+
+    #[test]
+    fn missing_values_sort_last() {
+        assert_eq!(sorted("res", MIXED_CODECS).last().unwrap(), "unknown.m3u8");
+        assert_eq!(sorted("+res", MIXED_CODECS).last().unwrap(), "unknown.m3u8");
+        assert_eq!(sorted("+codec", MIXED_CODECS).last().unwrap(), "unknown.m3u8");
+    }
+
+    #[test]
+    fn bad_keys_rejected() {
+        let error = FormatSort::parse("res,size").unwrap_err();
+        assert_eq!(error, "unknown sort key 'size'; use res, br, fps or codec");
+        let error = FormatSort::parse("codec:mpeg2").unwrap_err();
+        assert_eq!(error, "unknown codec 'mpeg2'; use one of av1, vp9, h265, h264");
+        assert_eq!(
+            FormatSort::parse("res:tall").unwrap_err(),
+            "'res:tall' needs a number after ':'"
+        );
+        assert_eq!(FormatSort::parse(" , ").unwrap_err(), "no sort keys given");
+    }
+}
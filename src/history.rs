@@ -0,0 +1,83 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+use jiff::{tz::TimeZone, Timestamp, Zoned};
+use serde::{Deserialize, Serialize};
+
+use crate::api::TimeFormat;
+use crate::exit::{Code, Failure};
+
+/// One played video, stored as a line of JSON in the history file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Entry {
+    /// Seconds since the Unix epoch
+    pub(crate) timestamp: i64,
+    pub(crate) id: String,
+    pub(crate) title: String,
+}
+
+impl Entry {
+    pub(crate) fn new(id: &str, title: &str) -> Self {
+        Self { timestamp: Timestamp::now().as_second(), id: id.to_owned(), title: title.to_owned() }
+    }
+
+    /// Formats like a listing line, with the index used by `--from-history` in front.
+    pub(crate) fn to_human(&self, index: usize, time_format: TimeFormat) -> Result<String> {
+        let date = Zoned::new(Timestamp::from_second(self.timestamp)?, TimeZone::system());
+        let same_day = Zoned::now().date() == date.date();
+        let date_time = date.strftime(time_format.pattern(same_day));
+        Ok(format!("{index}: {} - (played {date_time}) {}", self.id, self.title))
+    }
+}
+
+fn path() -> Result<PathBuf> {
+    let dir = dirs::config_dir().ok_or_else(|| anyhow!("couldn't find config directory"))?;
+    Ok(dir.join("cbc-sl").join("history.jsonl"))
+}
+
+/// Append an entry to the history file, creating it if needed.
+pub(crate) fn record(entry: &Entry) -> Result<()> {
+    let path = path()?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("opening history file {}", path.display()))?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)?;
+    Ok(())
+}
+
+/// Load the history, most recent first. Lines that fail to parse are skipped.
+pub(crate) fn load() -> Result<Vec<Entry>> {
+    let path = path()?;
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+    };
+    let mut entries: Vec<Entry> =
+        contents.lines().filter_map(|line| serde_json::from_str(line).ok()).collect();
+    entries.reverse();
+    Ok(entries)
+}
+
+/// Look up an entry by its 1-based index, as shown by `--history`.
+pub(crate) fn get(index: usize) -> Result<Entry> {
+    let entries = load()?;
+    index
+        .checked_sub(1)
+        .and_then(|i| entries.into_iter().nth(i))
+        .ok_or_else(|| Failure::new(Code::InvalidInput, format!("no history entry {index}")).into())
+}
+
+pub(crate) fn clear() -> Result<()> {
+    match fs::remove_file(path()?) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
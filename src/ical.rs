@@ -0,0 +1,120 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use jiff::{Span, Timestamp};
+
+use crate::api::Node;
+
+/// How long to block out for events whose duration is only a placeholder.
+const DEFAULT_HOURS: i64 = 2;
+
+/// Write `nodes` to `path` as an iCalendar file with one event each. UIDs come from CBC's IDs,
+/// so importing an updated file again updates the events instead of duplicating them.
+pub(crate) fn write(path: &Path, nodes: &[Node]) -> Result<()> {
+    let stamp = format_time(Timestamp::now());
+    let mut out = String::new();
+    line(&mut out, "BEGIN:VCALENDAR");
+    line(&mut out, "VERSION:2.0");
+    line(&mut out, "PRODID:-//cbc-sl//cbc-sl//EN");
+    line(&mut out, "CALSCALE:GREGORIAN");
+    for node in nodes {
+        let start = node.timestamp()?;
+        let end = if node.has_placeholder_duration() {
+            start.checked_add(Span::new().hours(DEFAULT_HOURS))?
+        } else {
+            node.end()?
+        };
+        let url = crate::player_url(node.proper_id());
+        line(&mut out, "BEGIN:VEVENT");
+        line(&mut out, &format!("UID:{}@cbc-sl", node.id));
+        line(&mut out, &format!("DTSTAMP:{stamp}"));
+        line(&mut out, &format!("DTSTART:{}", format_time(start)));
+        line(&mut out, &format!("DTEND:{}", format_time(end)));
+        line(&mut out, &format!("SUMMARY:{}", escape(&node.title)));
+        line(&mut out, &format!("DESCRIPTION:{}", escape(&format!("Watch: {url}"))));
+        line(&mut out, &format!("URL:{url}"));
+        line(&mut out, "END:VEVENT");
+    }
+    line(&mut out, "END:VCALENDAR");
+    fs::write(path, out).with_context(|| format!("writing {}", path.display()))
+}
+
+/// UTC, in iCalendar's basic format.
+fn format_time(time: Timestamp) -> String {
+    time.strftime("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Escape a TEXT value per RFC 5545.
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace(['\n', '\r'], "\\n")
+}
+
+/// Append a content line, folded so no line is over 75 bytes, without splitting a character.
+fn line(out: &mut String, content: &str) {
+    let mut width = 0;
+    for c in content.chars() {
+        if width + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        out.push(c);
+        width += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn folded(content: &str) -> String {
+        let mut out = String::new();
+        line(&mut out, content);
+        out
+    }
+
+    #[test]
+    fn text_escaped() {
+        assert_eq!(escape("Diving, women's; final"), r"Diving\, women's\; final");
+        assert_eq!(escape(r"C:\path"), r"C:\\path");
+        assert_eq!(escape("one\r\ntwo\nthree\rfour"), "one\\ntwo\\nthree\\nfour");
+        assert_eq!(escape("\\,"), "\\\\\\,");
+    }
+
+    #[test]
+    fn short_lines_unfolded() {
+        assert_eq!(folded("SUMMARY:Diving"), "SUMMARY:Diving\r\n");
+        let exact = format!("SUMMARY:{}", "a".repeat(67));
+        assert_eq!(folded(&exact), format!("{exact}\r\n"));
+    }
+
+    #[test]
+    fn long_lines_folded_at_75_octets() {
+        let long = format!("SUMMARY:{}", "a".repeat(72));
+        assert_eq!(folded(&long), format!("SUMMARY:{}\r\n aaaaa\r\n", "a".repeat(67)));
+    }
+
+    #[test]
+    fn folding_keeps_characters_whole() {
+        // "é" would take octets 75 and 76, so it has to start the next line
+        let content = format!("SUMMARY:{}é", "a".repeat(66));
+        assert_eq!(folded(&content), format!("SUMMARY:{}\r\n é\r\n", "a".repeat(66)));
+
+        let title = "Plongeon : finale du 10 m féminin, où l'équipe du Québec a décroché \
+            l'argent à l'épreuve préférée des téléspectateurs";
+        let content = format!("SUMMARY:{}", escape(title));
+        let out = folded(&content);
+        let lines: Vec<_> = out.strip_suffix("\r\n").unwrap().split("\r\n").collect();
+        assert!(lines.len() > 1, "{out}");
+        for line in &lines {
+            assert!(line.len() <= 75, "{} octets: {line}", line.len());
+        }
+        assert!(lines[1..].iter().all(|line| line.starts_with(' ')));
+        assert_eq!(out.replace("\r\n ", ""), format!("{content}\r\n"));
+    }
+}
@@ -0,0 +1,117 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::debug::debug;
+
+/// IINA's command-line tool inside the standard app bundle. It may also be linked onto the PATH
+/// by Homebrew, or found under `~/Applications`.
+const APP_PATHS: &[&str] = &["/Applications/IINA.app/Contents/MacOS/iina-cli"];
+
+/// Play `url` in IINA, passing the headers, proxy and audio language on to its embedded mpv.
+/// `extra_args` go to iina-cli as-is, before the URL.
+pub(crate) fn play(
+    url: &str,
+    headers: &[(String, String)],
+    proxy: Option<&str>,
+    audio_language: Option<&str>,
+    extra_args: &[String],
+) -> Result<()> {
+    if let Some(proxy) = proxy {
+        if !proxy.starts_with("http://") && !proxy.starts_with("https://") {
+            bail!("IINA only supports HTTP proxies, not {proxy}");
+        }
+    }
+    match find_cli() {
+        Some(cli) => play_cli(&cli, url, headers, proxy, audio_language, extra_args),
+        None if Path::new("/Applications/IINA.app").exists() => {
+            if !extra_args.is_empty() {
+                eprintln!("ignoring --player-arg: it needs iina-cli, which wasn't found");
+            }
+            play_open(url, headers, proxy, audio_language)
+        }
+        None => {
+            bail!("couldn't find IINA; install it to /Applications or put iina-cli on your PATH")
+        }
+    }
+}
+
+fn find_cli() -> Option<PathBuf> {
+    let home =
+        dirs::home_dir().map(|home| home.join("Applications/IINA.app/Contents/MacOS/iina-cli"));
+    let on_path = env::var_os("PATH")
+        .into_iter()
+        .flat_map(|paths| env::split_paths(&paths).collect::<Vec<_>>())
+        .map(|dir| dir.join("iina-cli"));
+    APP_PATHS.iter().map(PathBuf::from).chain(home).chain(on_path).find(|path| path.is_file())
+}
+
+fn play_cli(
+    cli: &Path,
+    url: &str,
+    headers: &[(String, String)],
+    proxy: Option<&str>,
+    audio_language: Option<&str>,
+    extra_args: &[String],
+) -> Result<()> {
+    debug!("iina cli={}", cli.display());
+    let mut cmd = Command::new(cli);
+    let mut fields = Vec::new();
+    for (name, value) in headers {
+        if name.eq_ignore_ascii_case("User-Agent") {
+            cmd.arg(format!("--mpv-user-agent={value}"));
+        } else {
+            fields.push(format!("{name}: {value}"));
+        }
+    }
+    if !fields.is_empty() {
+        cmd.arg(format!("--mpv-http-header-fields={}", fields.join(",")));
+    }
+    if let Some(proxy) = proxy {
+        cmd.arg(format!("--mpv-http-proxy={proxy}"));
+    }
+    if let Some(language) = audio_language {
+        cmd.arg(format!("--mpv-alang={language}"));
+    }
+    let status = cmd.args(extra_args).arg(url).status().context("running iina-cli")?;
+    if !status.success() {
+        return Err(anyhow!("iina-cli exited with {status}"));
+    }
+    Ok(())
+}
+
+/// Without the CLI, hand IINA a playlist that wraps the stream with VLC-style options for the
+/// headers. IINA passes these through to mpv.
+fn play_open(
+    url: &str,
+    headers: &[(String, String)],
+    proxy: Option<&str>,
+    audio_language: Option<&str>,
+) -> Result<()> {
+    let mut playlist = String::from("#EXTM3U\n");
+    for (name, value) in headers {
+        match name.to_ascii_lowercase().as_str() {
+            "user-agent" => playlist.push_str(&format!("#EXTVLCOPT:http-user-agent={value}\n")),
+            "referer" => playlist.push_str(&format!("#EXTVLCOPT:http-referrer={value}\n")),
+            _ => debug!("iina skipped header={name}"),
+        }
+    }
+    if let Some(proxy) = proxy {
+        playlist.push_str(&format!("#EXTVLCOPT:http-proxy={proxy}\n"));
+    }
+    if let Some(language) = audio_language {
+        playlist.push_str(&format!("#EXTVLCOPT:audio-language={language}\n"));
+    }
+    playlist.push_str(url);
+    playlist.push('\n');
+    let path = env::temp_dir().join("cbc-sl-iina.m3u8");
+    fs::write(&path, playlist).with_context(|| format!("writing {}", path.display()))?;
+    let status = Command::new("open").arg("-a").arg("IINA").arg(&path).status()?;
+    if !status.success() {
+        return Err(anyhow!("open -a IINA exited with {status}"));
+    }
+    Ok(())
+}
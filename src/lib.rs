@@ -0,0 +1,23 @@
+//! The parts of cbc-sl that can be used as a library: the types for CBC.ca's GraphQL listings
+//! and player pages in [api], the errors from getting a video's stream in [error], and the exit
+//! codes the `cbc-sl` binary uses in [exit].
+//!
+//! ```
+//! let body = r#"{"data": {"allContentItems": {"nodes": [{
+//!     "id": 1, "url": "https://www.cbc.ca/player/play/video/1.7276395", "title": "Diving",
+//!     "flag": "Live", "publishedAt": "1722000000000", "updatedAt": "1722000000000",
+//!     "type": "video", "media": {"duration": 5400, "hasCaptions": true, "streamType": "Live"}
+//! }]}}}"#;
+//! let response: cbc_sl::api::GqlResponse = serde_json::from_str(body)?;
+//! for node in &response.data.all_content_items.nodes {
+//!     println!("{} {}", node.proper_id(), node.title);
+//! }
+//! # Ok::<(), serde_json::Error>(())
+//! ```
+
+pub mod api;
+pub mod error;
+pub mod exit;
+mod log;
+
+pub use crate::log::set_debug_logger;
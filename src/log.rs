@@ -0,0 +1,26 @@
+use std::fmt;
+
+use once_cell::sync::OnceCell;
+
+static LOGGER: OnceCell<fn(fmt::Arguments<'_>)> = OnceCell::new();
+
+/// Send the library's debug lines to `logger`: which way a page's player state was found,
+/// listing entries that were skipped and the like, as `key=value` pairs. Nothing is logged
+/// unless this is called, and only the first logger set is used.
+pub fn set_debug_logger(logger: fn(fmt::Arguments<'_>)) {
+    let _ = LOGGER.set(logger);
+}
+
+pub(crate) fn logger() -> Option<fn(fmt::Arguments<'_>)> {
+    LOGGER.get().copied()
+}
+
+/// Log a line through the logger given to [set_debug_logger], if there is one.
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if let Some(logger) = $crate::log::logger() {
+            logger(format_args!($($arg)*));
+        }
+    };
+}
+pub(crate) use debug;
@@ -1,52 +1,478 @@
-use std::path::PathBuf;
-use std::process::Command;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::net::{Ipv6Addr, SocketAddr, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode};
+use std::time::{Duration, Instant};
 
-use anyhow::{anyhow, ensure, Context, Result};
-use clap::Parser;
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use clap::{ArgGroup, Parser, Subcommand, ValueEnum};
 use extend::ext;
-use hls_m3u8::{tags::VariantStream, MasterPlaylist};
+use hls_m3u8::tags::{ExtXMedia, VariantStream};
+use hls_m3u8::types::{MediaType, StreamData};
+use hls_m3u8::{MasterPlaylist, MediaPlaylist};
 use lazy_regex::{lazy_regex, regex};
 use once_cell::sync::Lazy;
-use owo_colors::{OwoColorize, Stream::Stdout};
+use owo_colors::{
+    OwoColorize,
+    Stream::{Stderr, Stdout},
+};
 use regex::Regex;
+use serde::Serialize;
 use serde_json::json;
-use ureq::{Agent, AgentBuilder, Proxy};
+use terminal_size::{terminal_size, Width};
+use ureq::{Agent, AgentBuilder, Middleware, MiddlewareNext, Proxy, Request, Response};
 use url::Url;
 
-use crate::api::{InitialState, Stream};
+use cbc_sl::{api, error, exit};
 
-mod api;
+use crate::api::{InitialState, Stream, TimeFormat};
+use crate::debug::debug;
+use crate::error::CbcError;
+use crate::exit::{Code, Failure};
+use crate::format_sort::FormatSort;
+use crate::quality::Quality;
+
+mod account;
+mod cast;
+mod cookies;
+mod dai;
+mod debug;
+mod format_sort;
+mod history;
+mod ical;
+mod iina;
+mod offline;
+mod pool;
+mod presets;
+mod quality;
+mod radiocanada;
+mod retry;
+mod shortcut;
+mod stream_cache;
+mod streamlink;
+#[cfg(unix)]
+mod unixsignals;
 #[cfg(windows)]
 mod wincolors;
+#[cfg(windows)]
+mod winjob;
 
 // pretend to be a real browser
 const USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 \
 (KHTML, like Gecko) Chrome/127.0.0.0 Safari/537.36";
 
-static ID_REGEX: Lazy<Regex> =
-    lazy_regex!(r#"(?:https://www\.cbc\.ca/player/play/video/)?([[:digit:]]+\.[[:digit:]]+)"#);
+/// Video IDs look like `1.7276395`. Old player IDs are a single long number, e.g. `2684119183`.
+static ID_REGEX: Lazy<Regex> = lazy_regex!(r#"^(?:[[:digit:]]+\.[[:digit:]]+|[[:digit:]]{6,})$"#);
 
 #[derive(Debug, Parser)]
 #[clap(version)]
 #[clap(about)]
-struct Args {
-    /// Proxy to use (if you aren't in Canada). If no scheme is set, defaults to socks5
-    #[clap(short = 'p', long = "proxy")]
-    proxy: Option<String>,
-    /// Don't run streamlink, just print the stream URL. Note that CBC.ca requires a matching
-    /// User-Agent or it will reject your request
-    #[clap(short = 'n', long = "no-run", conflicts_with_all(&["list", "replays"]))]
-    no_run: bool,
-    /// List available Olympics streams (at most page-size are shown)
-    #[clap(short = 'l', long = "list", conflicts_with_all(&["url", "replays"]))]
+#[clap(subcommand_negates_reqs = true, arg_required_else_help = true)]
+struct Cli {
+    #[clap(flatten)]
+    global: Global,
+    #[clap(subcommand)]
+    command: Option<Cmd>,
+    /// Without a subcommand, `cbc-sl URL` is the same as `cbc-sl play URL`
+    #[clap(flatten)]
+    play: PlayArgs,
+    #[clap(short = 'l', long = "list", hide = true, conflicts_with_all(&["replays", "all_events"]))]
     list: bool,
-    /// List available Olympics replays (at most page-size are shown)
-    #[clap(short = 'a', long = "replays", conflicts_with_all(&["url", "list"]))]
+    #[clap(short = 'a', long = "replays", hide = true, conflicts_with = "all_events")]
     replays: bool,
+    #[clap(short = 'A', long = "all-events", hide = true)]
+    all_events: bool,
+}
+
+#[derive(Debug, Subcommand)]
+enum Cmd {
+    /// List available Olympics streams, or CBC Sports ones with --sports (at most page-size
+    /// are shown)
+    List(ListArgs),
+    /// List available Olympics replays, or CBC Sports ones with --sports (at most page-size
+    /// are shown)
+    Replays(ListArgs),
+    /// List everything in one schedule: live now, upcoming, then replays
+    All(ListArgs),
+    /// Play a video with streamlink
+    Play(PlayArgs),
+    /// Don't run streamlink, just print the stream URL. Note that CBC.ca requires a matching
+    /// User-Agent or it will reject your request
+    Url(PlayArgs),
+    /// List recently played videos saved with --save-history (at most page-size are shown)
+    History(HistoryArgs),
+    /// Check that everything needed to watch works: streamlink, reaching CBC (through --proxy,
+    /// if set), being seen as in Canada, and getting a stream
+    Selftest(SelftestArgs),
+    /// Sign in to a free CBC account, for videos that need one. The password is asked for
+    /// unless $CBC_SL_PASSWORD is set, and only the resulting tokens are saved
+    Login(LoginArgs),
+    /// Print the version, and with --verbose, what's useful to include in a bug report
+    Version(VersionArgs),
+}
+
+/// Options accepted before or after any subcommand.
+#[derive(Debug, clap::Args)]
+struct Global {
+    /// Proxy to use (if you aren't in Canada). If no scheme is set, defaults to socks5
+    #[clap(
+        short = 'p',
+        long = "proxy",
+        env = "CBC_SL_PROXY",
+        hide_env_values = true,
+        global = true
+    )]
+    proxy: Option<String>,
+    /// User-Agent to send to CBC and streamlink. CBC rejects requests from anything that doesn't
+    /// look like a browser
+    #[clap(long = "user-agent", env = "CBC_SL_USER_AGENT", default_value = USER_AGENT, global = true)]
+    user_agent: String,
+    /// Don't give streamlink a User-Agent, and send an empty one to CBC (the HTTP library always
+    /// sends the header). For finding out what CBC insists on
+    #[clap(long = "no-user-agent", global = true)]
+    no_user_agent: bool,
+    /// Don't send a Referer anywhere, even one given with --header. For finding out what CBC
+    /// insists on
+    #[clap(long = "no-referer", global = true)]
+    no_referer: bool,
+    /// Extra HTTP header as 'Name: Value', sent to CBC and passed to streamlink. Can be repeated.
+    /// An empty value (e.g. 'Referer:') removes that header from what streamlink sends. Host
+    /// can't be set, and --user-agent wins over a User-Agent given here
+    #[clap(
+        short = 'H',
+        long = "header",
+        value_name = "HEADER",
+        value_parser(parse_header),
+        global = true
+    )]
+    headers: Vec<(String, String)>,
+    /// Extra query parameter as 'key=value' for the medianet request that gives the stream URL,
+    /// replacing any with the same name. Can be repeated. For when CBC changes that request
+    /// before cbc-sl is updated
+    #[clap(
+        long = "medianet-param",
+        value_name = "KEY=VALUE",
+        value_parser(parse_query_param),
+        global = true
+    )]
+    medianet_params: Vec<(String, String)>,
+    /// Cookies to send to CBC and pass to streamlink, for content that needs you to be signed
+    /// in: a Netscape-format cookies.txt or a JSON export, from a browser where you're logged
+    /// in. Cookies CBC sets are saved back to it, readable only by you
+    #[clap(long = "cookies", value_name = "PATH", global = true)]
+    cookies: Option<PathBuf>,
+    /// Don't keep the cookies CBC sets, even for the rest of this run
+    #[clap(long = "no-cookies", global = true, conflicts_with = "cookies")]
+    no_cookies: bool,
     /// Size of a "page" of streams to load. Since this tool only loads one page, this means
-    /// how many streams/replays to show for --list and --replays
-    #[clap(long = "page-size", default_value = "24")]
+    /// how many streams/replays to show when listing. A note says so when CBC has more
+    #[clap(long = "page-size", default_value = "24", global = true)]
     page_size: u8,
+    /// Include live events that started up to this many hours ago
+    #[clap(
+        long = "hours-back",
+        value_name = "N",
+        default_value = "14",
+        value_parser = clap::value_parser!(u16).range(0..=720),
+        global = true
+    )]
+    hours_back: u16,
+    /// Include live events starting up to this many days from now
+    #[clap(
+        long = "days-ahead",
+        value_name = "N",
+        default_value = "35",
+        value_parser = clap::value_parser!(u16).range(0..=365),
+        global = true
+    )]
+    days_ahead: u16,
+    /// List CBC Sports live streams and replays in general, rather than the Olympics
+    #[clap(long = "sports", global = true)]
+    sports: bool,
+    /// Plain output for logs and old terminals: no colors, and only ASCII, so accented letters
+    /// lose their accents and symbols are spelled out
+    #[clap(long = "ascii", global = true)]
+    ascii: bool,
+    /// Show full URLs when listing events
+    #[clap(short = 'f', long = "full-urls", global = true)]
+    full_urls: bool,
+    /// Template for listing lines, using {id}, {url}, {title}, {date}, {status}, {duration},
+    /// {note}, {cc} and {show}. The default is "{id} - {note}{cc} {title}{show}"
+    #[clap(long = "format", value_name = "TEMPLATE", global = true)]
+    format: Option<String>,
+    /// Clock to use for times in listings
+    #[clap(long = "time-format", value_enum, default_value = "24", global = true)]
+    time_format: TimeFormat,
+    /// Let long titles wrap instead of shortening them to fit the terminal
+    #[clap(long = "no-truncate", global = true)]
+    no_truncate: bool,
+    /// How many requests to make at once when fetching several things, like thumbnails
+    #[clap(long = "concurrency", value_name = "N", default_value = "4", global = true)]
+    concurrency: usize,
+    /// How many times to retry a request when CBC rate-limits us
+    #[clap(long = "retries", value_name = "N", default_value = "2", global = true)]
+    retries: u32,
+    /// Connect over IPv4 first when a host has both kinds of address. Doesn't affect lookups a
+    /// SOCKS proxy does for us
+    #[clap(long = "prefer-ipv4", global = true, conflicts_with = "prefer_ipv6")]
+    prefer_ipv4: bool,
+    /// Connect over IPv6 first when a host has both kinds of address
+    #[clap(long = "prefer-ipv6", global = true)]
+    prefer_ipv6: bool,
+    /// Who looks up hostnames with a SOCKS --proxy: the proxy (remote), or this computer (local),
+    /// whatever the proxy's scheme says. streamlink always follows this; cbc-sl's own requests
+    /// can only do remote DNS through SOCKS5, so there local only affects SOCKS4
+    #[clap(long = "dns", value_enum, default_value = "remote", global = true)]
+    dns: Dns,
+    /// Longest to wait, in seconds, before retrying when CBC rate-limits us
+    #[clap(long = "max-retry-wait", value_name = "SECS", default_value = "30", global = true)]
+    max_retry_wait: u64,
+    /// Log every HTTP request and response (URL, headers, status, timing) to stderr. Tokens in
+    /// URLs and cookies are hidden
+    #[clap(long = "http-debug", global = true)]
+    http_debug: bool,
+    /// Like --http-debug, but show URLs in full, tokens included. Cookies stay hidden
+    #[clap(long = "http-debug-full", global = true)]
+    http_debug_full: bool,
+    /// Log request URLs, response sizes and parsing decisions to stderr
+    #[clap(long = "debug", global = true)]
+    debug: bool,
+    /// Write the raw player page and JSON payloads to this directory, for attaching to bug reports
+    #[clap(long = "debug-dump", value_name = "DIR", global = true)]
+    debug_dump: Option<PathBuf>,
+    /// Save the player page, player state, stream JSON and master playlist to this directory,
+    /// with tokens hidden and the time in their names, then say which to attach to a bug report
+    #[clap(long = "dump-state", value_name = "DIR", global = true)]
+    dump_state: Option<PathBuf>,
+    /// Make no requests: read the player page, stream JSON and master playlist from files
+    /// instead (as saved by --debug-dump), to see what cbc-sl makes of them. Only for
+    /// `cbc-sl url` or --no-run
+    #[clap(long = "offline", requires = "page_file", global = true)]
+    offline: bool,
+    /// The player page to use with --offline
+    #[clap(long = "page-file", value_name = "FILE", requires = "offline", global = true)]
+    page_file: Option<PathBuf>,
+    /// The medianet JSON to use with --offline
+    #[clap(long = "stream-file", value_name = "FILE", requires = "offline", global = true)]
+    stream_file: Option<PathBuf>,
+    /// The master playlist to use with --offline
+    #[clap(long = "playlist-file", value_name = "FILE", requires = "offline", global = true)]
+    playlist_file: Option<PathBuf>,
+}
+
+impl Global {
+    /// The `--header`s that apply, leaving out User-Agent, which `--user-agent` sets, and
+    /// Referer with `--no-referer`.
+    fn extra_headers(&self) -> Vec<(String, String)> {
+        self.headers
+            .iter()
+            .filter(|(name, _)| !is_user_agent(name))
+            .filter(|(name, _)| !(self.no_referer && name.eq_ignore_ascii_case("Referer")))
+            .cloned()
+            .collect()
+    }
+
+    /// The User-Agent to send, or `None` with `--no-user-agent`.
+    fn user_agent(&self) -> Option<&str> {
+        (!self.no_user_agent).then_some(self.user_agent.as_str())
+    }
+
+    /// The width to fit listing lines into, or `None` with `--no-truncate`. Falls back to 100
+    /// columns when the width can't be found, e.g. when piped.
+    fn line_width(&self) -> Option<usize> {
+        if self.no_truncate {
+            return None;
+        }
+        Some(terminal_size().map_or(100, |(Width(width), _)| width.into()))
+    }
+
+    /// `--proxy` in the form streamlink's `--http-proxy` takes.
+    fn streamlink_proxy(&self) -> Option<String> {
+        self.proxy.as_deref().map(|spec| proxy_url_streamlink(spec, self.dns))
+    }
+
+    fn ip_family(&self) -> Option<IpFamily> {
+        match (self.prefer_ipv4, self.prefer_ipv6) {
+            (true, _) => Some(IpFamily::V4),
+            (_, true) => Some(IpFamily::V6),
+            _ => None,
+        }
+    }
+
+    fn catalog(&self) -> &'static Catalog {
+        if self.sports {
+            &SPORTS
+        } else {
+            &OLYMPICS
+        }
+    }
+
+    fn listing_format(&self) -> &str {
+        match &self.format {
+            Some(format) => format,
+            None if self.full_urls => api::FULL_URL_FORMAT,
+            None => api::DEFAULT_FORMAT,
+        }
+    }
+}
+
+/// Where CBC files the live streams and replays for something we can list. Following another
+/// event should only need another one of these.
+#[derive(Debug)]
+struct Catalog {
+    /// Category slugs for live and upcoming streams. Empty means any category
+    live_categories: &'static [&'static str],
+    /// Category slugs for replays. Empty means any category
+    replay_categories: &'static [&'static str],
+    /// Only keep results CBC filed under this section. The API can't filter on sections, so
+    /// this is done after fetching
+    section: Option<&'static str>,
+}
+
+const OLYMPICS: Catalog = Catalog {
+    live_categories: &["summer-olympics-live"],
+    replay_categories: &["summer-olympics-replays"],
+    section: None,
+};
+
+/// CBC Sports outside the Games: curling, athletics, soccer and so on. Those are spread over
+/// many categories that come and go, so rely on the section instead.
+const SPORTS: Catalog =
+    Catalog { live_categories: &[], replay_categories: &[], section: Some("Sports") };
+
+impl Catalog {
+    /// The `itemsQueryFilters` for a listing, restricted to `categories` if there are any.
+    fn filters(&self, categories: &[&str], mut filters: serde_json::Value) -> serde_json::Value {
+        if !categories.is_empty() {
+            filters["categorySlugs"] = json!(categories);
+        }
+        filters
+    }
+
+    /// Drop results from outside [Self::section].
+    fn retain(&self, response: &mut api::GqlResponse) {
+        if let Some(section) = self.section {
+            let nodes = &mut response.data.all_content_items.nodes;
+            let before = nodes.len();
+            nodes.retain(|node| node.in_section(section));
+            debug!("section_filter section={section} dropped={}", before - nodes.len());
+        }
+    }
+}
+
+#[derive(Debug, Default, clap::Args)]
+struct ListArgs {
+    /// Only show events whose title contains this text
+    #[clap(long = "filter", value_name = "TEXT")]
+    filter: Option<String>,
+    /// Only show events for this sport, by category or title, e.g. swimming. Can be repeated to
+    /// show any of several
+    #[clap(long = "sport", value_name = "SPORT")]
+    sport: Vec<String>,
+    /// Add the sports from this preset in the presets file (presets.json in cbc-sl's config
+    /// directory), as if passed with --sport
+    #[clap(long = "preset", value_name = "NAME")]
+    preset: Option<String>,
+    /// Include live events that have already ended
+    #[clap(long = "show-ended")]
+    show_ended: bool,
+    /// Only show events that have captions
+    #[clap(long = "captions-only")]
+    captions_only: bool,
+    /// Only print how many events match. Exits with code 3 if there are none
+    #[clap(long = "count")]
+    count: bool,
+    /// Download each event's thumbnail into this directory
+    #[clap(long = "thumbnail", value_name = "DIR")]
+    thumbnail: Option<PathBuf>,
+    /// Print each event as a JSON object on its own line
+    #[clap(long = "json-lines", conflicts_with = "count")]
+    json_lines: bool,
+    /// Print events as CSV with a header row: id, url, state, start, duration_seconds, title,
+    /// show_name
+    #[clap(long = "csv", conflicts_with_all(&["count", "json_lines"]))]
+    csv: bool,
+    /// Save the upcoming events to this iCalendar (.ics) file instead of listing them, for
+    /// importing into a calendar app. Importing again updates the events
+    #[clap(long = "ics", value_name = "FILE", conflicts_with_all(&["count", "json_lines", "csv"]))]
+    ics: Option<PathBuf>,
+    /// Also save events that are live now with --ics
+    #[clap(long = "ics-include-live", requires = "ics")]
+    ics_include_live: bool,
+    /// Only print `key value` lines for monitoring: how many events are live and upcoming, and
+    /// the seconds until the next one starts (left out if nothing is upcoming)
+    #[clap(
+        long = "metrics",
+        conflicts_with_all(&["count", "json_lines", "csv", "ics", "thumbnail"])
+    )]
+    metrics: bool,
+    /// Include descriptions and categories in --json-lines output
+    #[clap(long = "full", requires = "json_lines")]
+    full: bool,
+    /// How to order events. Live listings default to `status`; replays and --json-lines keep
+    /// CBC's order unless this is given
+    #[clap(long = "sort", value_enum)]
+    sort: Option<SortOrder>,
+}
+
+impl ListArgs {
+    /// The `--sport`s to show, with those from `--preset` added, in lowercase.
+    fn sports(&self) -> Result<Vec<String>> {
+        let mut sports = self.sport.clone();
+        if let Some(name) = &self.preset {
+            sports.extend(presets::get(name)?.sport);
+        }
+        Ok(sports.iter().map(|sport| sport.to_lowercase()).collect())
+    }
+
+    fn sort_order(&self, listing: Listing) -> Option<SortOrder> {
+        match listing {
+            Listing::Live if !self.json_lines => Some(self.sort.unwrap_or(SortOrder::Status)),
+            _ => self.sort,
+        }
+    }
+}
+
+#[derive(Copy, Debug, Clone, PartialEq, Eq, ValueEnum)]
+enum SortOrder {
+    /// Live now (newest first), then upcoming (soonest first), then ended (newest first)
+    Status,
+    /// By start time, earliest first
+    Start,
+    /// CBC's order
+    Api,
+}
+
+#[derive(Debug, clap::Args)]
+#[clap(group(ArgGroup::new("recording").args(["output", "output_template"])))]
+#[clap(group(ArgGroup::new("restarting").args(["output", "output_template", "auto_restart"]).multiple(true)))]
+struct PlayArgs {
+    /// CBC.ca URL or ID. Required unless using --latest or --from-history
+    #[clap(value_parser(probably_cbc))]
+    url: Option<String>,
+    /// More videos to play alongside URL, with --multi
+    #[clap(value_name = "URL", value_parser(probably_cbc), requires = "multi")]
+    more_urls: Vec<String>,
+    /// Play whichever live event started most recently
+    #[clap(long = "latest", conflicts_with_all(&["url", "from_history"]))]
+    latest: bool,
+    /// Only pick from events whose title contains this text, with --latest
+    #[clap(long = "filter", value_name = "TEXT", requires = "latest")]
+    filter: Option<String>,
+    /// With --latest, download the event's thumbnail into this directory
+    #[clap(long = "thumbnail", value_name = "DIR", requires = "latest")]
+    thumbnail: Option<PathBuf>,
+    /// Play the video with this index from `cbc-sl history`
+    #[clap(long = "from-history", value_name = "INDEX", conflicts_with = "url")]
+    from_history: Option<usize>,
+    /// With `cbc-sl url`, print the stream URL, headers and title as a JSON object. With
+    /// --print-metadata, print the metadata as JSON
+    #[clap(long = "json")]
+    json: bool,
+    /// Same as `cbc-sl url`
+    #[clap(short = 'n', long = "no-run", hide = true)]
+    no_run: bool,
     /// Streamlink log level
     #[clap(long = "loglevel", value_parser(["none", "error", "warning", "info", "debug", "trace"]), default_value = "info")]
     loglevel: String,
@@ -54,21 +480,267 @@ struct Args {
     /// versions of streamlink. This shouldn't do anything on versions >3.1.1.
     #[clap(short = 'T', long = "distrust-streamlink")]
     distrust: bool,
-    /// Stream quality to request. Won't work if you're using --distrust-streamlink
-    #[clap(short = 'q', long = "quality", default_value = "best")]
-    quality: String,
+    /// Stream quality to request, such as best, worst or 720p. A comma-separated list is tried
+    /// in order. With --distrust-streamlink, only best, worst and resolutions can be used.
+    /// Defaults to $CBC_SL_QUALITY, or best
+    #[clap(short = 'q', long = "quality", value_parser(parse_quality))]
+    quality: Option<String>,
+    /// Play the highest-bandwidth stream at or under this many bits per second, e.g. 4000000.
+    /// Picks the stream itself, like --distrust-streamlink
+    #[clap(long = "max-bandwidth", value_name = "BPS", conflicts_with_all(&["quality", "audio_only"]))]
+    max_bandwidth: Option<u64>,
+    /// Pick the stream ourselves by these keys in order, like yt-dlp's --format-sort: res, br
+    /// (kbit/s in limits), fps and codec. Higher is preferred; prefix + for lower, or add :LIMIT
+    /// for the closest at or under it, e.g. res:1080,br,codec:h264
+    #[clap(long = "format-sort", value_name = "KEYS", value_parser(FormatSort::parse), conflicts_with_all(&["quality", "audio_only"]))]
+    format_sort: Option<FormatSort>,
+    /// Check --quality against the qualities the stream actually has before running streamlink
+    #[clap(long = "strict-quality", conflicts_with = "audio_only")]
+    strict_quality: bool,
+    /// Only play audio, for listening to commentary in the background
+    #[clap(long = "audio-only", conflicts_with = "quality")]
+    audio_only: bool,
+    /// Pick the commentary track in this language, e.g. fr or en, when the stream has more than
+    /// one. See --list-formats for the languages a stream has
+    #[clap(long = "audio-language", value_name = "CODE")]
+    audio_language: Option<String>,
+    /// Try to play streams even if they look DRM-protected
+    #[clap(long = "ignore-drm")]
+    ignore_drm: bool,
+    /// Write streamlink's log to this file instead of the terminal
+    #[clap(long = "log-file", value_name = "PATH")]
+    log_file: Option<PathBuf>,
+    /// Append to --log-file instead of overwriting it
+    #[clap(long = "log-append", requires = "log_file")]
+    log_append: bool,
+    /// When the video finishes, play the first recommended clip, and so on
+    #[clap(long = "autoplay", alias = "play-next")]
+    autoplay: bool,
+    /// Stop --autoplay after this many extra clips
+    #[clap(long = "max-autoplay", value_name = "N", default_value = "10", requires = "autoplay")]
+    max_autoplay: u32,
+    /// Don't send a Referer header to streamlink. Same as --header 'Referer:'
+    #[clap(long = "referer-none")]
+    referer_none: bool,
+    /// Program to play the stream with. IINA is for macOS, and skips streamlink entirely
+    #[clap(long = "player", value_enum, default_value = "streamlink")]
+    player: Player,
+    /// Extra argument for the player, e.g. --player-arg=--pause. Repeat for more; they're
+    /// passed in the order given, each as a single argument, so no extra quoting is needed.
+    /// With streamlink they go in --player-args, with IINA they go straight to iina-cli
+    #[clap(long = "player-arg", value_name = "ARG", allow_hyphen_values = true)]
+    player_args: Vec<String>,
+    /// Have streamlink write the stream to stdout and pipe it into this command instead of
+    /// a player, e.g. "ffplay -"
+    #[clap(long = "stdout", alias = "pipe", value_name = "COMMAND", conflicts_with = "player")]
+    pipe: Option<String>,
+    /// Print what the video is (title, description, times, duration, assets) and exit, without
+    /// fetching the stream. This works even when geo-blocked. Combine with --json for JSON
+    #[clap(long = "print-metadata", conflicts_with_all(&["autoplay", "show_assets"]))]
+    print_metadata: bool,
+    /// Print the video's assets (type, key and options) and exit
+    #[clap(long = "show-assets", conflicts_with = "autoplay")]
+    show_assets: bool,
+    /// Type of asset to get the stream from. See --show-assets for what a video has
+    #[clap(long = "asset", value_name = "TYPE", default_value = "medianet")]
+    asset: String,
+    /// Play the video's DAI stream, ads and all, instead of medianet when it has one. Videos
+    /// without a medianet asset use DAI anyway
+    #[clap(long = "prefer-dai")]
+    prefer_dai: bool,
+    /// Fetch the player page and stream URL again, rather than reusing what cbc-sl found for the
+    /// same video in the last two minutes
+    #[clap(long = "refresh")]
+    refresh: bool,
+    /// Print the asset URL the stream would be fetched from (and the DAI one, if any) and exit,
+    /// without fetching it
+    #[clap(long = "resolve-only", conflicts_with_all(&["autoplay", "show_assets", "print_metadata"]))]
+    resolve_only: bool,
+    /// Print the qualities the stream has (resolution, bandwidth, frame rate and codecs) and
+    /// exit
+    #[clap(long = "list-formats", alias = "list-qualities", conflicts_with_all(&["autoplay", "show_assets", "print_metadata", "resolve_only"]))]
+    list_formats: bool,
+    /// If the stream or its playlist isn't published yet (HTTP 404), keep trying instead of
+    /// failing. Live events often start a minute or two before their stream is ready
+    #[clap(long = "wait-for-stream")]
+    wait_for_stream: bool,
+    /// How many times to try with --wait-for-stream
+    #[clap(
+        long = "wait-attempts",
+        value_name = "N",
+        default_value = "10",
+        requires = "wait_for_stream"
+    )]
+    wait_attempts: u32,
+    /// Seconds between tries with --wait-for-stream
+    #[clap(
+        long = "wait-interval",
+        value_name = "SECS",
+        default_value = "15",
+        requires = "wait_for_stream"
+    )]
+    wait_interval: u64,
+    /// Record the stream to this file with streamlink instead of playing it. If streamlink fails
+    /// while the event should still be live, it's restarted with a fresh stream URL, writing to
+    /// FILE.1.ts, FILE.2.ts and so on
+    #[clap(short = 'o', long = "output", value_name = "FILE", conflicts_with_all(&["pipe", "player", "autoplay"]))]
+    output: Option<PathBuf>,
+    /// Record like --output, naming the file from the video: {title}, {id}, {date} (when it was
+    /// published) and {ext} are filled in, made safe for file names. Without a template, uses
+    /// {date}_{title}.{ext}
+    #[clap(long = "output-template", value_name = "TEMPLATE", num_args(0..=1), require_equals(true), default_missing_value(api::DEFAULT_OUTPUT_TEMPLATE), conflicts_with_all(&["output", "pipe", "player", "autoplay"]))]
+    output_template: Option<String>,
+    /// If streamlink fails while the event should still be live, wait a few seconds, get a fresh
+    /// stream URL and start it again. Recordings with --output always do this
+    #[clap(long = "auto-restart", conflicts_with_all(&["pipe", "cast"]))]
+    auto_restart: bool,
+    /// Give up on --auto-restart or a --output recording after restarting this many times
+    #[clap(long = "max-restarts", value_name = "N", default_value = "5", requires = "restarting")]
+    max_restarts: u32,
+    /// Stop streamlink and fail if it hasn't started playing (or writing --output) after this
+    /// many seconds, rather than waiting forever on a dead stream
+    #[clap(long = "launch-timeout", value_name = "SECS", conflicts_with = "cast")]
+    launch_timeout: Option<u64>,
+    /// Play all the URLs given at once, each with its own streamlink and player, e.g. two finals
+    /// side by side. streamlink's output is tagged with which stream it's from, and each player's
+    /// window title says which it is. If one fails the others keep going
+    #[clap(long = "multi", requires = "url", conflicts_with_all(&["latest", "from_history", "autoplay", "pipe", "cast", "recording", "auto_restart", "shortcut", "print_metadata", "show_assets", "resolve_only", "list_formats"]))]
+    multi: bool,
+    /// With --multi, stop every stream as soon as one fails
+    #[clap(long = "fail-fast", requires = "multi")]
+    fail_fast: bool,
+    /// Cast to a Chromecast with catt instead of playing locally. Uses catt's default device
+    /// unless one is named with --cast=DEVICE. Only works for streams that play without
+    /// cbc-sl's headers
+    #[clap(long = "cast", value_name = "DEVICE", num_args(0..=1), require_equals(true), conflicts_with_all(&["player", "pipe", "player_args", "recording"]))]
+    cast: Option<Option<String>>,
     /// Streamlink bin name or path
-    #[clap(short = 'S', long = "streamlink", default_value = "streamlink")]
+    #[clap(
+        short = 'S',
+        long = "streamlink",
+        env = "CBC_SL_STREAMLINK",
+        default_value = "streamlink"
+    )]
     streamlink: PathBuf,
-    /// Show full URLs when listing events
-    #[clap(short = 'f', long = "full-urls")]
-    full_urls: bool,
-    /// CBC.ca URL or ID
-    #[clap(value_parser(probably_cbc), required_unless_present_any(["list", "replays"]))]
-    url: Option<String>,
+    /// Save a shortcut to the video's CBC page here instead of playing it. The kind of shortcut
+    /// comes from the extension: .url (Windows), .desktop (Linux) or .webloc (macOS)
+    #[clap(long = "shortcut", value_name = "PATH", conflicts_with_all(&["autoplay", "print_metadata", "show_assets", "resolve_only", "cast", "recording", "pipe"]))]
+    shortcut: Option<PathBuf>,
+    /// Point the --shortcut at the stream itself rather than the CBC page. A .desktop shortcut
+    /// runs streamlink with the right headers; the others only have the URL. Stream URLs expire,
+    /// so these don't last long
+    #[clap(long = "shortcut-stream", requires = "shortcut")]
+    shortcut_stream: bool,
+    /// Remember the video in the history file. History is only saved when this is passed
+    #[clap(long = "save-history")]
+    save_history: bool,
+}
+
+impl PlayArgs {
+    /// Whether we choose the variant rather than leaving it to streamlink.
+    fn picks_variant(&self) -> bool {
+        self.distrust || self.max_bandwidth.is_some() || self.format_sort.is_some()
+    }
+
+    /// The quality to pass streamlink along with the stream. When we've picked the variant, it's
+    /// the only stream streamlink will find there, and `--quality` has already been applied.
+    fn streamlink_quality(&self) -> &str {
+        if self.picks_variant() {
+            "best"
+        } else {
+            self.quality()
+        }
+    }
+
+    /// The quality to ask streamlink for. Audio-only streams are named `audio_only` or `audio`
+    /// depending on how the playlist is laid out, so fall back through both.
+    fn quality(&self) -> &str {
+        if self.audio_only {
+            "audio_only,audio,worst"
+        } else {
+            self.quality.as_deref().unwrap_or(&DEFAULT_QUALITY)
+        }
+    }
+}
+
+/// The quality used when `--quality` isn't given. This isn't read by clap like the other
+/// `CBC_SL_*` variables, since clap would then treat it as conflicting with `--audio-only`.
+static DEFAULT_QUALITY: Lazy<String> =
+    Lazy::new(|| std::env::var("CBC_SL_QUALITY").unwrap_or_else(|_| "best".to_owned()));
+
+#[derive(Copy, Debug, Clone, PartialEq, Eq, ValueEnum)]
+enum Player {
+    Streamlink,
+    Iina,
 }
 
-fn get_live_and_upcoming(agent: &Agent, page_size: u8) -> Result<api::GqlResponse> {
+#[derive(Debug, clap::Args)]
+struct HistoryArgs {
+    /// Delete the history file instead of listing it
+    #[clap(long = "clear")]
+    clear: bool,
+}
+
+#[derive(Debug, clap::Args)]
+struct LoginArgs {
+    /// The account's email address. Asked for if not given
+    #[clap(long = "email", env = "CBC_SL_EMAIL")]
+    email: Option<String>,
+    /// Forget the saved sign-in instead
+    #[clap(long = "logout", conflicts_with = "email")]
+    logout: bool,
+}
+
+#[derive(Debug, clap::Args)]
+struct VersionArgs {
+    /// Also print the platform, which streamlink is found and its version, whether colors are
+    /// on, and the User-Agent
+    #[clap(short = 'v', long = "verbose")]
+    verbose: bool,
+    /// Streamlink bin name or path
+    #[clap(
+        short = 'S',
+        long = "streamlink",
+        env = "CBC_SL_STREAMLINK",
+        default_value = "streamlink"
+    )]
+    streamlink: PathBuf,
+}
+
+#[derive(Debug, clap::Args)]
+struct SelftestArgs {
+    /// Streamlink bin name or path
+    #[clap(
+        short = 'S',
+        long = "streamlink",
+        env = "CBC_SL_STREAMLINK",
+        default_value = "streamlink"
+    )]
+    streamlink: PathBuf,
+    /// Instead of the usual checks, time a few requests through --proxy (or directly, without
+    /// one) and report how many worked and the median latency, for choosing between proxies
+    #[clap(long = "proxy-test")]
+    proxy_test: bool,
+    /// The URL --proxy-test requests
+    #[clap(
+        long = "proxy-test-url",
+        value_name = "URL",
+        default_value = "https://www.cbc.ca/",
+        requires = "proxy_test"
+    )]
+    proxy_test_url: String,
+    /// How many requests --proxy-test makes
+    #[clap(
+        long = "proxy-test-count",
+        value_name = "N",
+        default_value = "5",
+        value_parser(clap::value_parser!(u32).range(1..)),
+        requires = "proxy_test"
+    )]
+    proxy_test_count: u32,
+}
+
+fn get_live_and_upcoming(agent: &Agent, global: &Global) -> Result<api::GqlResponse> {
     const LIVE_QUERY: &str =
         "query contentItemsByItemsQueryFilters($itemsQueryFilters:ItemsQueryFilters\
     ,$page:Int,$pageSize:Int,$minPubDate:String,$maxPubDate:String,$lineupOnly:Boolean,$offset:Int)\
@@ -86,31 +758,31 @@ fn get_live_and_upcoming(agent: &Agent, page_size: u8) -> Result<api::GqlRespons
     ContentItem{publishedAt mediaId sourceId media{duration hasCaptions streamType}title \
     imageLarge}}primary secondary tertiary}categories{name slug path}}";
 
+    let catalog = global.catalog();
     let query = json!({
         "query": LIVE_QUERY,
         "variables": {
             "lineupOnly": false,
             "page": 1,
-            "pageSize": page_size,
-            "maxPubDate": "now+35d",
-            "minPubDate": "now-14h",
-            "itemsQueryFilters": {
+            "pageSize": global.page_size,
+            "maxPubDate": format!("now+{}d", global.days_ahead),
+            "minPubDate": format!("now-{}h", global.hours_back),
+            "itemsQueryFilters": catalog.filters(catalog.live_categories, json!({
                 "types": [
                     "video"
                 ],
-                "categorySlugs": [
-                    "summer-olympics-live"
-                ],
                 "sort": "+publishedAt",
                 "mediaStreamType": "Live"
-            }
+            }))
         }
     });
 
-    Ok(agent.post("https://www.cbc.ca/graphql").send_json(query)?.into_json()?)
+    let mut response = post_graphql(agent, query, "graphql-live.json")?;
+    catalog.retain(&mut response);
+    Ok(response)
 }
 
-fn get_replays(agent: &Agent, page_size: u8) -> Result<api::GqlResponse> {
+fn get_replays(agent: &Agent, global: &Global) -> Result<api::GqlResponse> {
     const VOD_QUERY: &str = "query contentItemsByItemsQueryFilters($itemsQueryFilters:\
     ItemsQueryFilters,$page:Int,$pageSize:Int,$minPubDate:String,$maxPubDate:String,\
     $lineupOnly:Boolean,$offset:Int){allContentItems(itemsQueryFilters:$itemsQueryFilters,\
@@ -128,171 +800,2513 @@ fn get_replays(agent: &Agent, page_size: u8) -> Result<api::GqlResponse> {
     mediaId sourceId media{duration hasCaptions streamType}title imageLarge}}primary secondary \
     tertiary}categories{name slug path}}";
 
+    let catalog = global.catalog();
     let query = json!({
         "query": VOD_QUERY,
         "variables": {
             "lineupOnly": false,
             "page": 1,
-            "pageSize": page_size,
-            "itemsQueryFilters": {
+            "pageSize": global.page_size,
+            "itemsQueryFilters": catalog.filters(catalog.replay_categories, json!({
                 "types": [
                     "video"
                 ],
-                "sort": "-publishedAt",
-                "categorySlugs": [
-                    "summer-olympics-replays"
-                ]
-            }
+                "sort": "-publishedAt"
+            }))
         }
     });
-    Ok(agent.post("https://www.cbc.ca/graphql").send_json(query)?.into_json()?)
+    let mut response = post_graphql(agent, query, "graphql-replays.json")?;
+    catalog.retain(&mut response);
+    Ok(response)
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
-    #[cfg(windows)]
-    let _ = wincolors::enable_colors();
-    let mut ab = AgentBuilder::new().user_agent(USER_AGENT);
-    if let Some(proxy) = args.proxy.as_deref() {
-        ab = ab.proxy(Proxy::new(proxy_url_ureq(proxy))?);
+/// Apply the listing filters: live events that have already ended are dropped unless asked not to,
+/// titles must contain the `--filter` text, with `--captions-only` there must be captions, and
+/// with `--sport` or `--preset` it must be one of those sports.
+fn filter_listing(nodes: Vec<api::Node>, args: &ListArgs) -> Result<Vec<api::Node>> {
+    let filter = args.filter.as_deref().map(str::to_lowercase);
+    let sports = args.sports()?;
+    let mut kept = Vec::with_capacity(nodes.len());
+    for item in nodes {
+        if !args.show_ended && item.has_ended()? {
+            continue;
+        }
+        if args.captions_only && !item.media.has_captions {
+            continue;
+        }
+        if filter.as_ref().is_some_and(|f| !item.title.to_lowercase().contains(f)) {
+            continue;
+        }
+        if !sports.is_empty() && !sports.iter().any(|sport| item.is_sport(sport)) {
+            continue;
+        }
+        kept.push(item);
     }
-    let agent = ab.build();
-    let psz = args.page_size;
-    if args.list {
-        for item in get_live_and_upcoming(&agent, psz)?.data.all_content_items.nodes {
-            println!("{}", item.to_human(args.full_urls)?);
+    Ok(kept)
+}
+
+fn sort_listing(nodes: Vec<api::Node>, order: Option<SortOrder>) -> Result<Vec<api::Node>> {
+    let Some(order) = order else { return Ok(nodes) };
+    let mut keyed = Vec::with_capacity(nodes.len());
+    for item in nodes {
+        let start = item.timestamp()?.as_millisecond();
+        let key = match order {
+            SortOrder::Api => (0, 0),
+            SortOrder::Start => (0, start),
+            SortOrder::Status => match item.status()? {
+                api::LiveStatus::Live | api::LiveStatus::OpenEnded => (0, -start),
+                api::LiveStatus::Upcoming => (1, start),
+                api::LiveStatus::Ended => (2, -start),
+            },
+        };
+        keyed.push((key, item));
+    }
+    // stable, so ties keep CBC's order
+    keyed.sort_by_key(|(key, _)| *key);
+    Ok(keyed.into_iter().map(|(_, item)| item).collect())
+}
+
+/// Print a line per event. An event that can't be formatted gets a note in its place rather
+/// than hiding the rest.
+fn print_listing(nodes: &[api::Node], global: &Global) {
+    for item in nodes {
+        match item.to_human(global.listing_format(), global.time_format, global.line_width()) {
+            Ok(line) => println!("{line}"),
+            Err(e) => {
+                println!("{} - (couldn't read this event: {e}) {}", item.proper_id(), item.title)
+            }
+        }
+    }
+}
+
+/// Find the live event that started most recently, for `--latest`. If nothing is live, the next
+/// few upcoming events are listed instead.
+fn pick_latest(agent: &Agent, global: &Global, args: &PlayArgs) -> Result<String> {
+    let nodes = get_live_and_upcoming(agent, global)?.data.all_content_items.nodes;
+    let filter = ListArgs { filter: args.filter.clone(), ..Default::default() };
+    let nodes = filter_listing(nodes, &filter)?;
+    let mut latest: Option<(&api::Node, jiff::Timestamp)> = None;
+    for item in &nodes {
+        if item.is_live()? {
+            let start = item.timestamp()?;
+            if latest.is_none_or(|(_, t)| start > t) {
+                latest = Some((item, start));
+            }
         }
-        return Ok(());
     }
-    if args.replays {
-        for item in get_replays(&agent, psz)?.data.all_content_items.nodes {
-            println!("{}", item.to_human(args.full_urls)?);
+    if let Some((item, _)) = latest {
+        println!(
+            "Playing {}",
+            item.to_human(global.listing_format(), global.time_format, global.line_width())?
+        );
+        if let Some(dir) = &args.thumbnail {
+            save_thumbnails(agent, std::slice::from_ref(item), dir, 1)?;
         }
-        return Ok(());
+        return Ok(item.proper_id().to_owned());
     }
+    println!("Nothing is live right now. Coming up:");
+    for item in nodes.iter().filter(|item| !item.has_ended().unwrap_or(true)).take(5) {
+        println!(
+            "{}",
+            item.to_human(global.listing_format(), global.time_format, global.line_width())?
+        );
+    }
+    bail!(Failure::new(Code::NoStreams, "nothing is live"))
+}
 
-    let id = parse_cbc_id(&args.url.unwrap())?;
-
-    let target = format!("https://www.cbc.ca/player/play/video/{id}");
-    let page = agent.get(&target).call()?.into_string()?;
-    let preload_json_regex = regex!(r#"window\.__INITIAL_STATE__ = (.*);</script>"#);
-    let preload_json = preload_json_regex
-        .captures(&page)
-        .ok_or_else(|| anyhow!("couldn't find initial state!"))?
-        .get(1)
-        .unwrap()
-        .as_str();
-    let initial_state: InitialState = serde_json::from_str(preload_json)?;
-    let surls = initial_state.video.get_stream_urls();
-    let json_url = surls.medianet.ok_or_else(|| anyhow!("no medianet URL found"))?;
-
-    let blocked = format!(
-        "grabbing stream data; an error here probably means {}",
-        "your IP is geo-blocked".if_supports_color(Stdout, |text| text.bright_red().on_black()),
-    );
+/// Download the largest image of each node into `dir`, named by ID. Existing files are left
+/// alone, and a node without images or a failed download only prints a warning.
+fn save_thumbnails(
+    agent: &Agent,
+    nodes: &[api::Node],
+    dir: &Path,
+    concurrency: usize,
+) -> Result<()> {
+    fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    pool::map(nodes, concurrency, |item| {
+        let Some(image) = item.largest_image() else {
+            debug!("thumbnail missing id={}", item.proper_id());
+            return;
+        };
+        let ext = Path::new(image.fileurl.split('?').next().unwrap_or_default())
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("jpg");
+        let path = dir.join(format!("{}.{ext}", item.proper_id()));
+        if path.exists() {
+            return;
+        }
+        if let Err(e) = download(agent, &image.fileurl, &path) {
+            let _ = fs::remove_file(&path);
+            eprintln!("couldn't save thumbnail for {}: {e:#}", item.proper_id());
+        }
+    });
+    Ok(())
+}
 
-    let stream_json: Stream = agent.get(&json_url).call()?.into_json().context(blocked)?;
-    let master_url = stream_json.url.as_str();
+fn download(agent: &Agent, url: &str, path: &Path) -> Result<()> {
+    debug!("request method=GET url={url}");
+    let resp = retry::call(agent.get(url), None).map_err(CbcError::from)?;
+    let mut file = File::create(path)?;
+    let bytes = io::copy(&mut resp.into_reader(), &mut file)?;
+    debug!("saved file={} bytes={bytes}", path.display());
+    Ok(())
+}
 
-    let stream = if args.distrust {
-        let playlist = agent.get(master_url).call()?.into_string()?;
-        get_best_stream(master_url, &playlist)?
-    } else {
-        master_url.to_owned()
-    };
-    if args.no_run {
-        println!("User-Agent: {}", USER_AGENT);
-        println!("URL: {}", stream);
-    } else {
-        let sl = args.streamlink;
-        let mut cmd = Command::new(sl);
-        cmd.arg("--loglevel")
-            .arg(&args.loglevel)
-            .arg("--http-header")
-            .arg(format!("User-Agent={USER_AGENT}"))
-            .arg("--http-header")
-            .arg(format!("Referer={target}"));
-        let stat = if let Some(proxy) = args.proxy.map(|p| proxy_url_streamlink(&p)) {
-            cmd.arg("--http-proxy").arg(&proxy).arg(stream).arg(args.quality).status()?
-        } else {
-            cmd.arg(stream).arg(args.quality).status()?
+/// Print each node as a line of JSON, flushing as we go so consumers can process them
+/// incrementally. A `kind` field is added if given.
+fn print_json_lines(nodes: &[api::Node], full: bool, kind: Option<&str>) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+    for item in nodes {
+        let mut value = item.to_json(full)?;
+        if let (Some(kind), Some(map)) = (kind, value.as_object_mut()) {
+            map.insert("kind".to_owned(), kind.into());
+        }
+        serde_json::to_writer(&mut stdout, &value)?;
+        writeln!(stdout)?;
+        stdout.flush()?;
+    }
+    Ok(())
+}
+
+/// For `--ics`: save the upcoming events to a calendar file, and those live now too with
+/// `include_live`.
+fn save_ics(path: &Path, nodes: &[api::Node], include_live: bool) -> Result<()> {
+    let mut events = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        let wanted = match node.status()? {
+            api::LiveStatus::Upcoming => true,
+            api::LiveStatus::Live | api::LiveStatus::OpenEnded => include_live,
+            api::LiveStatus::Ended => false,
         };
-        if !stat.success() {
-            return if stat.code().is_some() {
-                Err(anyhow!("streamlink exit code: {}", stat.code().unwrap()))
-            } else {
-                Err(anyhow!("streamlink exited unexpectedly"))
-            };
+        if wanted {
+            events.push(node.clone());
         }
     }
+    ical::write(path, &events)?;
+    println!("Saved {} events to {}", events.len(), path.display());
     Ok(())
 }
 
-/// Given the URL of the master playlist, and its contents, get the highest-bandwidth stream
-/// and build an absolute URL to it.
-///
-/// Workaround for https://github.com/streamlink/streamlink/issues/4329
-fn get_best_stream(url: &str, mp: &str) -> Result<String> {
-    let best = parse_master_playlist(mp)?;
-    let mut url = Url::parse(url)?;
-    url.set_query(None);
-    url.path_segments_mut().unwrap().pop();
-    Ok(format!("{}/{}", url.as_str(), best))
+/// The `--csv` columns. Only ever add to the end, since spreadsheets refer to them by position.
+const CSV_COLUMNS: &[&str] =
+    &["id", "url", "state", "start", "duration_seconds", "title", "show_name"];
+
+/// Print `nodes` as RFC 4180 CSV, with CRLF line endings as Excel expects.
+fn print_csv(nodes: &[api::Node]) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+    write!(stdout, "{}\r\n", CSV_COLUMNS.join(","))?;
+    for item in nodes {
+        let state = match item.flag {
+            api::Flag::Video | api::Flag::Unknown => "replay",
+            api::Flag::Live => match item.status()? {
+                api::LiveStatus::Live | api::LiveStatus::OpenEnded => "live",
+                api::LiveStatus::Upcoming => "upcoming",
+                api::LiveStatus::Ended => "ended",
+            },
+        };
+        let row = [
+            item.proper_id().to_owned(),
+            player_url(item.proper_id()),
+            state.to_owned(),
+            item.timestamp()?.to_string(),
+            item.media.duration.to_string(),
+            item.title.clone(),
+            item.show_name.clone().unwrap_or_default(),
+        ];
+        let row: Vec<_> = row.iter().map(|field| csv_field(field)).collect();
+        write!(stdout, "{}\r\n", row.join(","))?;
+    }
+    stdout.flush()?;
+    Ok(())
 }
 
-/// Parse a master playlist, return the URI of the stream with the highest bandwidth.
-fn parse_master_playlist(input: &str) -> Result<String> {
-    let mp = MasterPlaylist::try_from(input)?;
-    let mut variant = mp.variant_streams;
-    ensure!(!variant.is_empty(), "no streams found");
-    variant.sort_by_key(|v| v.bandwidth());
-    variant.reverse();
-    let best = variant.first().unwrap();
-    Ok(best.uri())
+/// Quote a CSV field if it needs it, doubling any quotes inside.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
 }
 
-#[ext]
-impl VariantStream<'_> {
-    fn uri(&self) -> String {
-        match self {
-            Self::ExtXStreamInf { uri, .. } | Self::ExtXIFrame { uri, .. } => uri.to_string(),
+/// Print the number of matching events for `--count`, failing if there are none so it can be
+/// used in shell conditionals.
+fn print_count(count: usize) -> Result<()> {
+    println!("{count}");
+    ensure!(count > 0, Failure::new(Code::NoStreams, "nothing matched"));
+    Ok(())
+}
+
+/// `--metrics`: counters in the `key value` form Prometheus' text format and most scrapers accept.
+fn print_metrics(nodes: &[api::Node]) -> Result<()> {
+    let now = jiff::Timestamp::now();
+    let (mut live, mut upcoming, mut next) = (0, 0, None::<i64>);
+    for node in nodes {
+        if node.is_live()? {
+            live += 1;
+        } else if node.status()? == api::LiveStatus::Upcoming {
+            upcoming += 1;
+            let wait = node.timestamp()?.as_second() - now.as_second();
+            next = Some(next.map_or(wait, |next| next.min(wait)));
         }
     }
+    println!("cbc_sl_live_events {live}");
+    println!("cbc_sl_upcoming_events {upcoming}");
+    if let Some(next) = next {
+        println!("cbc_sl_seconds_until_next_event {next}");
+    }
+    Ok(())
 }
 
-/// Rewrites proxy specifications:
-/// * SOCKS4 is changed to specify remote DNS
-/// * SOCKS5 strips the `h` if present, since ureq always does remote DNS and can't handle `SOCKS5H`
-/// * Missing scheme becomes` socks5://`
-fn proxy_url_ureq(spec: &str) -> String {
-    // We may need remote DNS to avoid geoblocking (ureq always does remote DNS with SOCKS5)
-    let mut spec = spec.replacen("socks5h:", "socks5:", 1).replacen("socks4:", "socks4a:", 1);
-    if !spec.contains("://") {
-        spec = format!("socks5://{}", spec);
+/// Fetch live/upcoming streams and replays concurrently. Any replay that also appears in the
+/// live listing is dropped from the replays. Also says whether either listing had more than a
+/// page.
+fn get_all_events(
+    agent: &Agent,
+    global: &Global,
+) -> Result<(Vec<api::Node>, Vec<api::Node>, bool)> {
+    let listings = [Listing::Live, Listing::Replays];
+    let mut fetched = pool::map(&listings, global.concurrency, |listing| match listing {
+        Listing::Live => get_live_and_upcoming(agent, global),
+        Listing::Replays => get_replays(agent, global),
+    })
+    .into_iter();
+    let live = fetched.next().unwrap()?.data.all_content_items;
+    let replays = fetched.next().unwrap()?.data.all_content_items;
+    let more = live.has_more(global.page_size.into()) || replays.has_more(global.page_size.into());
+    let (live, mut replays) = (live.nodes, replays.nodes);
+    let before = replays.len();
+    replays.retain(|r| !live.iter().any(|l| l.id == r.id));
+    if replays.len() < before {
+        debug!("dropped_live_replays count={}", before - replays.len());
     }
-    spec
+    Ok((live, replays, more))
 }
 
-/// Rewrites proxy specifications:
-/// * SOCKS4/5 is changed to specify remote DNS
-/// * Missing scheme becomes `socks5h://`
-fn proxy_url_streamlink(spec: &str) -> String {
-    let mut spec = spec.replacen("socks5:", "socks5h:", 1).replacen("socks4:", "socks4a:", 1);
-    if !spec.contains("://") {
-        spec = format!("socks5h://{}", spec);
+/// POST a GraphQL query to CBC and parse the response. `dump_name` is the file the raw response
+/// is saved as when using `--debug-dump`.
+fn post_graphql(
+    agent: &Agent,
+    query: serde_json::Value,
+    dump_name: &str,
+) -> Result<api::GqlResponse> {
+    const URL: &str = "https://www.cbc.ca/graphql";
+    debug!("request method=POST url={URL}");
+    let resp = retry::call(agent.post(URL), Some(&query)).map_err(CbcError::from)?;
+    let status = resp.status();
+    let body = resp.into_string()?;
+    debug!("response status={status} bytes={} url={URL}", body.len());
+    debug::dump(dump_name, &body);
+    let mut response: api::GqlResponse = serde_json::from_str(&body)?;
+    for id in &response.data.all_content_items.skipped {
+        let note = format!("skipping an event CBC sent that couldn't be read (id {id})");
+        eprintln!("{}", note.if_supports_color(Stderr, |text| text.dimmed()));
     }
-    spec
+    dedupe_nodes(&mut response.data.all_content_items.nodes);
+    Ok(response)
 }
 
-/// Returns OK if the input is either numeric (ID) or a full CBC URL.
-fn probably_cbc(input: &str) -> std::result::Result<String, String> {
-    if let Some(cap) = ID_REGEX.captures(input) {
-        Ok(cap.get(1).unwrap().as_str().to_string())
+/// CBC sometimes returns the same event twice, differing only in `updatedAt`. Keep the most
+/// recently updated copy of each, in the position the first one appeared.
+fn dedupe_nodes(nodes: &mut Vec<api::Node>) {
+    let before = nodes.len();
+    let mut kept: Vec<api::Node> = Vec::with_capacity(before);
+    for node in nodes.drain(..) {
+        match kept.iter_mut().find(|k| k.id == node.id) {
+            Some(existing) if node.updated() > existing.updated() => *existing = node,
+            Some(_) => {}
+            None => kept.push(node),
+        }
+    }
+    if kept.len() < before {
+        debug!("dropped_duplicates count={}", before - kept.len());
+    }
+    *nodes = kept;
+}
+
+/// GET `url` and return the body, logging the exchange when debugging. `dump_name` is the file
+/// the body is saved as when using `--debug-dump`.
+fn get_string(agent: &Agent, url: &str, dump_name: &str) -> Result<String, CbcError> {
+    get_page(agent, url, dump_name).map(|(body, _)| body)
+}
+
+/// [get_string], also returning the URL the request ended up at after any redirects.
+fn get_page(agent: &Agent, url: &str, dump_name: &str) -> Result<(String, String), CbcError> {
+    debug!("request method=GET url={url}");
+    let resp = retry::call(agent.get(url), None)?;
+    let status = resp.status();
+    let final_url = resp.get_url().to_owned();
+    if final_url != url {
+        debug!("redirected url={url} to={final_url}");
+    }
+    let body = resp.into_string()?;
+    debug!("response status={status} bytes={} url={url}", body.len());
+    debug::dump(dump_name, &body);
+    Ok((body, final_url))
+}
+
+fn main() -> ExitCode {
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(e) if e.use_stderr() => {
+            let _ = e.print();
+            return ExitCode::from(Code::InvalidInput as u8);
+        }
+        Err(e) => e.exit(),
+    };
+    match run(&cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            print_error(&e);
+            ExitCode::from(exit::code_for(&e))
+        }
+    }
+}
+
+/// Print the error that ended the run, with geo-blocking picked out in red since it's the
+/// failure people most need to notice.
+fn print_error(e: &anyhow::Error) {
+    let message = format!("Error: {e:?}");
+    let geo_blocked = e
+        .chain()
+        .any(|cause| matches!(cause.downcast_ref::<CbcError>(), Some(CbcError::GeoBlocked { .. })));
+    if !geo_blocked {
+        eprintln!("{message}");
+        return;
+    }
+    let colored = error::GEO_BLOCKED.if_supports_color(Stderr, |text| text.bright_red().on_black());
+    eprintln!("{}", message.replacen(error::GEO_BLOCKED, &colored.to_string(), 1));
+}
+
+fn run(cli: &Cli) -> Result<()> {
+    let global = &cli.global;
+    debug::init(global.debug, global.debug_dump.clone())?;
+    if let Some(dir) = &global.dump_state {
+        debug::init_state_dump(dir.clone())?;
+    }
+    retry::init(global.max_retry_wait, global.retries);
+    if global.ascii {
+        owo_colors::set_override(false);
+        api::set_ascii();
+    }
+    #[cfg(windows)]
+    let _ = wincolors::enable_colors();
+    let mut ab = AgentBuilder::new().user_agent(global.user_agent().unwrap_or_default());
+    let mut resolver = Resolver { prefer: global.ip_family(), ..Default::default() };
+    if let Some(page) = global.page_file.as_deref().filter(|_| global.offline) {
+        offline::init(page, global.stream_file.as_deref(), global.playlist_file.as_deref())?;
+        if global.proxy.is_some() {
+            eprintln!("Not using --proxy: --offline reads saved files and makes no requests.");
+        }
+    } else if let Some(proxy) = global.proxy.as_deref() {
+        ab = with_ureq_proxy(ab, proxy, global.dns, &mut resolver)?;
+    }
+    ab = ab.resolver(resolver);
+    if global.headers.iter().any(|(name, _)| is_user_agent(name)) {
+        eprintln!("Ignoring the User-Agent --header; use --user-agent to change it.");
+    }
+    let headers = global.extra_headers();
+    if global.http_debug || global.http_debug_full {
+        eprintln!("[http] headers for every request:");
+        eprintln!("[http]   User-Agent: {}", global.user_agent().unwrap_or("(empty)"));
+        for (name, value) in headers.iter().filter(|(_, value)| !value.is_empty()) {
+            eprintln!("[http]   {name}: {value}");
+        }
+    }
+    if !headers.is_empty() {
+        ab = ab.middleware(ExtraHeaders(headers));
+    }
+    if !global.no_cookies {
+        ab = ab.middleware(cookies::init(global.cookies.as_deref())?);
+    }
+    // last, so it sees the headers the other middleware add
+    if global.http_debug || global.http_debug_full {
+        ab = ab.middleware(debug::HttpLogger { full: global.http_debug_full });
+    }
+    if global.offline {
+        ab = ab.middleware(offline::NoNetwork);
+    }
+    let agent = ab.build();
+
+    let legacy_list = ListArgs::default();
+    let result = match &cli.command {
+        Some(Cmd::List(args)) => list(&agent, global, args, Listing::Live),
+        Some(Cmd::Replays(args)) => list(&agent, global, args, Listing::Replays),
+        Some(Cmd::All(args)) => list_all(&agent, global, args),
+        Some(Cmd::Play(args)) => play_command(&agent, global, args, false),
+        Some(Cmd::Url(args)) => play_command(&agent, global, args, true),
+        Some(Cmd::History(args)) => history_command(global, args),
+        Some(Cmd::Selftest(args)) => selftest(&agent, global, args),
+        Some(Cmd::Login(args)) if args.logout => account::logout(),
+        Some(Cmd::Login(args)) => account::login(&agent, args.email.as_deref()),
+        Some(Cmd::Version(args)) => version(global, args),
+        None if cli.list => list(&agent, global, &legacy_list, Listing::Live),
+        None if cli.replays => list(&agent, global, &legacy_list, Listing::Replays),
+        None if cli.all_events => list_all(&agent, global, &legacy_list),
+        None => play_command(&agent, global, &cli.play, cli.play.no_run),
+    };
+    if let Err(e) = cookies::jar().map_or(Ok(()), |jar| jar.save()) {
+        eprintln!("couldn't save cookies: {e:#}");
+    }
+    if global.dump_state.is_some() {
+        let files = debug::state_files();
+        if files.is_empty() {
+            eprintln!("Nothing was fetched for --dump-state to save.");
+        } else {
+            eprintln!("For a bug report, attach these files (tokens in them have been hidden):");
+            for file in files {
+                eprintln!("  {}", file.display());
+            }
+        }
+    }
+    if global.offline {
+        return result
+            .context("in --offline mode, which only reads the given files (no network, no proxy)");
+    }
+    result
+}
+
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+enum Listing {
+    Live,
+    Replays,
+}
+
+fn list(agent: &Agent, global: &Global, args: &ListArgs, listing: Listing) -> Result<()> {
+    let items = match listing {
+        Listing::Live => get_live_and_upcoming(agent, global)?.data.all_content_items,
+        Listing::Replays => get_replays(agent, global)?.data.all_content_items,
+    };
+    let more = items.has_more(global.page_size.into());
+    let nodes = sort_listing(filter_listing(items.nodes, args)?, args.sort_order(listing))?;
+    if let Some(dir) = &args.thumbnail {
+        save_thumbnails(agent, &nodes, dir, global.concurrency)?;
+    }
+    if args.count {
+        return print_count(nodes.len());
+    }
+    if args.metrics {
+        return print_metrics(&nodes);
+    }
+    if args.json_lines {
+        return print_json_lines(&nodes, args.full, None);
+    }
+    if args.csv {
+        return print_csv(&nodes);
+    }
+    if let Some(path) = &args.ics {
+        ensure!(
+            listing == Listing::Live,
+            Failure::new(Code::InvalidInput, "--ics only works for live and upcoming events")
+        );
+        return save_ics(path, &nodes, args.ics_include_live);
+    }
+    print_listing(&nodes, global);
+    if more {
+        note_more(nodes.len(), global);
+    }
+    Ok(())
+}
+
+/// `all`: one schedule in three sections, live now, upcoming, then replays. Live events that
+/// have ended go with the replays. With `--json-lines`, a `kind` field says which section each
+/// event is in instead.
+fn list_all(agent: &Agent, global: &Global, args: &ListArgs) -> Result<()> {
+    let (live, replays, more) = get_all_events(agent, global)?;
+    let live = sort_listing(filter_listing(live, args)?, args.sort_order(Listing::Live))?;
+    let replays = sort_listing(filter_listing(replays, args)?, args.sort_order(Listing::Replays))?;
+    let (mut airing, mut upcoming, mut ended) = (Vec::new(), Vec::new(), Vec::new());
+    for item in live {
+        match item.status()? {
+            api::LiveStatus::Live | api::LiveStatus::OpenEnded => airing.push(item),
+            api::LiveStatus::Upcoming => upcoming.push(item),
+            api::LiveStatus::Ended => ended.push(item),
+        }
+    }
+    ended.extend(replays);
+    let sections = [
+        ("Live now:", "live", airing),
+        ("Upcoming:", "upcoming", upcoming),
+        ("Replays:", "replay", ended),
+    ];
+    if let Some(dir) = &args.thumbnail {
+        for (_, _, nodes) in &sections {
+            save_thumbnails(agent, nodes, dir, global.concurrency)?;
+        }
+    }
+    if args.count {
+        return print_count(sections.iter().map(|(_, _, nodes)| nodes.len()).sum());
+    }
+    if args.metrics {
+        let [(_, _, airing), (_, _, upcoming), _] = &sections;
+        return print_metrics(&[airing.as_slice(), upcoming].concat());
+    }
+    if args.json_lines {
+        for (_, kind, nodes) in &sections {
+            print_json_lines(nodes, args.full, Some(kind))?;
+        }
+        return Ok(());
+    }
+    if args.csv {
+        let nodes: Vec<_> = sections.into_iter().flat_map(|(_, _, nodes)| nodes).collect();
+        return print_csv(&nodes);
+    }
+    if let Some(path) = &args.ics {
+        let [(_, _, airing), (_, _, upcoming), _] = &sections;
+        return save_ics(path, &[airing.as_slice(), upcoming].concat(), args.ics_include_live);
+    }
+    let mut first = true;
+    for (header, _, nodes) in sections.iter().filter(|(_, _, nodes)| !nodes.is_empty()) {
+        if !first {
+            println!();
+        }
+        first = false;
+        println!("{}", header.if_supports_color(Stdout, |text| text.bold()));
+        print_listing(nodes, global);
+    }
+    if more {
+        note_more(sections.iter().map(|(_, _, nodes)| nodes.len()).sum(), global);
+    }
+    Ok(())
+}
+
+/// After a listing, say that CBC has more than fit in one page and how to see them.
+fn note_more(shown: usize, global: &Global) {
+    let note = if global.page_size == u8::MAX {
+        format!("Showing {shown}; CBC has more, but {} is the most that can be asked for.", u8::MAX)
     } else {
-        Err("invalid url".into())
+        format!(
+            "Showing {shown}; CBC has more. Raise --page-size (now {}, at most {}) to see them.",
+            global.page_size,
+            u8::MAX
+        )
+    };
+    eprintln!("{note}");
+}
+
+fn history_command(global: &Global, args: &HistoryArgs) -> Result<()> {
+    if args.clear {
+        return history::clear();
+    }
+    for (i, entry) in history::load()?.iter().take(global.page_size.into()).enumerate() {
+        println!("{}", entry.to_human(i + 1, global.time_format)?);
     }
+    Ok(())
 }
 
-fn parse_cbc_id(input: &str) -> Result<String> {
-    Ok(ID_REGEX.captures(input).unwrap().get(1).unwrap().as_str().to_string())
+/// `selftest`: run through each thing playing depends on, printing whether it worked. Every
+/// check runs even if an earlier one failed, and the first failure is returned at the end.
+fn selftest(agent: &Agent, global: &Global, args: &SelftestArgs) -> Result<()> {
+    if args.proxy_test {
+        return proxy_test(agent, global, &args.proxy_test_url, args.proxy_test_count);
+    }
+    const CHECKS: usize = 4;
+    let mut failures = Vec::new();
+    let mut report = |name: &str, result: Result<String>| match result {
+        Ok(detail) => {
+            let mark = "ok".if_supports_color(Stdout, |text| text.green());
+            println!("[{mark}] {name}: {detail}");
+        }
+        Err(e) => {
+            let mark = "FAIL".if_supports_color(Stdout, |text| text.bright_red());
+            println!("[{mark}] {name}: {e:#}");
+            failures.push(e);
+        }
+    };
+
+    report("streamlink", streamlink_version(&args.streamlink));
+    let listing = get_live_and_upcoming(agent, global);
+    let detail = match &listing {
+        Ok(response) => Ok(format!(
+            "listed {} live and upcoming events{}",
+            response.data.all_content_items.nodes.len(),
+            if global.proxy.is_some() { " through the proxy" } else { "" }
+        )),
+        Err(e) => Err(anyhow!("{e:#}")),
+    };
+    report("CBC listing", detail);
+    report("location", geo_country(agent));
+    let live = listing.map(|r| r.data.all_content_items.nodes).unwrap_or_default();
+    report("stream", selftest_stream(agent, global, live));
+
+    let failed = failures.len();
+    match failures.into_iter().next() {
+        Some(first) => Err(first.context(format!("{failed} of {CHECKS} checks failed"))),
+        None => Ok(()),
+    }
+}
+
+/// `selftest --proxy-test`: request `url` `count` times, one after another, and report how many
+/// succeeded and the median time to a response. Each request goes through the same agent as
+/// everything else, so proxying and DNS behave as they would when watching.
+fn proxy_test(agent: &Agent, global: &Global, url: &str, count: u32) -> Result<()> {
+    let via = match &global.proxy {
+        Some(proxy) => format!("through {proxy}"),
+        None => "without a proxy".to_owned(),
+    };
+    println!("Requesting {url} {count} times {via}");
+    let mut latencies = Vec::new();
+    for i in 1..=count {
+        debug!("request method=GET url={url}");
+        let start = Instant::now();
+        let result = agent.get(url).call();
+        let elapsed = start.elapsed();
+        match result {
+            Ok(_) => {
+                println!("[{i}] {} ms", elapsed.as_millis());
+                latencies.push(elapsed);
+            }
+            Err(e) => println!("[{i}] failed: {:#}", CbcError::from(e)),
+        }
+    }
+    let succeeded = latencies.len();
+    latencies.sort();
+    let median = match succeeded {
+        0 => None,
+        n if n % 2 == 1 => Some(latencies[n / 2]),
+        n => Some((latencies[n / 2 - 1] + latencies[n / 2]) / 2),
+    };
+    let summary = format!("{succeeded} of {count} requests succeeded");
+    let Some(median) = median else {
+        bail!(Failure::new(Code::Network, summary));
+    };
+    println!("{summary}, median {} ms", median.as_millis());
+    Ok(())
+}
+
+/// For `version`: cbc-sl's version, and with `--verbose`, what a bug report needs about where
+/// it's running. All of it is found out now rather than when building.
+fn version(global: &Global, args: &VersionArgs) -> Result<()> {
+    println!("cbc-sl {}", env!("CARGO_PKG_VERSION"));
+    if !args.verbose {
+        return Ok(());
+    }
+    let field = |label: &str, value: &str| println!("{:<20} {value}", format!("{label}:"));
+    let platform = format!("{} {}", std::env::consts::OS, std::env::consts::ARCH);
+    field("Platform", &platform);
+    let path = find_program(&args.streamlink);
+    let path = path.as_deref().map_or("not found".into(), Path::to_string_lossy);
+    field("streamlink path", &path);
+    match streamlink_version(&args.streamlink) {
+        Ok(version) => field("streamlink version", &version),
+        Err(e) => field("streamlink version", &format!("unknown ({e:#})")),
+    }
+    // the same check colored output goes through, so --ascii and NO_COLOR count
+    let colored = "x".if_supports_color(Stdout, |text| text.red()).to_string() != "x";
+    field("Colors", if colored { "on" } else { "off" });
+    field("User-Agent", global.user_agent().unwrap_or("(empty)"));
+    Ok(())
+}
+
+/// Where `program` is run from: itself if it's a path, otherwise the first match on PATH.
+fn find_program(program: &Path) -> Option<PathBuf> {
+    if program.components().count() > 1 {
+        return program.is_file().then(|| program.to_owned());
+    }
+    let mut names = vec![program.to_owned()];
+    if cfg!(windows) && program.extension().is_none() {
+        names.insert(0, program.with_extension("exe"));
+    }
+    let dirs = std::env::var_os("PATH").map(|paths| std::env::split_paths(&paths).collect());
+    let dirs: Vec<PathBuf> = dirs.unwrap_or_default();
+    dirs.iter().flat_map(|dir| names.iter().map(|name| dir.join(name))).find(|path| path.is_file())
+}
+
+/// The first line of `streamlink --version`.
+fn streamlink_version(streamlink: &Path) -> Result<String> {
+    let output = match Command::new(streamlink).arg("--version").output() {
+        Ok(output) => output,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let message = format!("couldn't find {}; is it installed?", streamlink.display());
+            return Err(
+                anyhow::Error::new(e).context(Failure::new(Code::StreamlinkMissing, message))
+            );
+        }
+        Err(e) => return Err(e).with_context(|| format!("running {}", streamlink.display())),
+    };
+    ensure!(
+        output.status.success(),
+        "{} --version exited with {}",
+        streamlink.display(),
+        output.status
+    );
+    let version = String::from_utf8_lossy(&output.stdout);
+    Ok(version.lines().next().unwrap_or_default().trim().to_owned())
+}
+
+/// Which country the outside world thinks we're in, going by Cloudflare's trace endpoint, which
+/// sees the same address CBC does.
+fn geo_country(agent: &Agent) -> Result<String> {
+    const URL: &str = "https://www.cloudflare.com/cdn-cgi/trace";
+    debug!("request method=GET url={URL}");
+    let body = retry::call(agent.get(URL), None).map_err(CbcError::from)?.into_string()?;
+    let country = body
+        .lines()
+        .find_map(|line| line.strip_prefix("loc="))
+        .ok_or_else(|| anyhow!("couldn't tell from {URL}"))?;
+    ensure!(
+        country == "CA",
+        Failure::new(
+            Code::Geoblocked,
+            format!("seen as in {country}, but CBC's streams only work from CA; try --proxy")
+        )
+    );
+    Ok(country.to_owned())
+}
+
+/// Get the master playlist for something that should be playable: the first event airing now,
+/// or failing that the latest replay.
+fn selftest_stream(agent: &Agent, global: &Global, live: Vec<api::Node>) -> Result<String> {
+    let airing = live.into_iter().find(|node| {
+        matches!(node.status(), Ok(api::LiveStatus::Live | api::LiveStatus::OpenEnded))
+    });
+    let node = match airing {
+        Some(node) => node,
+        None => get_replays(agent, global)?
+            .data
+            .all_content_items
+            .nodes
+            .into_iter()
+            .next()
+            .ok_or_else(|| Failure::new(Code::NoStreams, "nothing live or replayable to try"))?,
+    };
+    let state = load_player(agent, &player_url(node.proper_id()))?;
+    let json_url = &state.video.asset("medianet")?.key;
+    let master_url = fetch_master_url(agent, global, json_url)?;
+    let playlist = get_string(agent, &master_url, "master.m3u8")?;
+    let variants = MasterPlaylist::try_from(playlist.as_str())?.variant_streams.len();
+    ensure!(variants > 0, Failure::new(Code::NoStreams, "the master playlist has no streams"));
+    Ok(format!("{} - {} ({variants} qualities)", node.proper_id(), node.title))
+}
+
+/// `play` and `url`: work out which video was asked for, then play it (and, with `--autoplay`,
+/// whatever is recommended after it).
+fn play_command(agent: &Agent, global: &Global, args: &PlayArgs, no_run: bool) -> Result<()> {
+    ensure!(
+        no_run || !global.offline,
+        Failure::new(Code::InvalidInput, "--offline only works with `cbc-sl url` or --no-run")
+    );
+    if no_run {
+        ensure!(
+            !args.save_history,
+            Failure::new(
+                Code::InvalidInput,
+                "--save-history can't be used without running streamlink"
+            )
+        );
+        ensure!(
+            args.log_file.is_none(),
+            Failure::new(Code::InvalidInput, "--log-file can't be used without running streamlink")
+        );
+        ensure!(
+            !args.autoplay,
+            Failure::new(Code::InvalidInput, "--autoplay can't be used without running streamlink")
+        );
+        ensure!(
+            args.output.is_none() && args.output_template.is_none(),
+            Failure::new(
+                Code::InvalidInput,
+                "--output and --output-template can't be used without running streamlink"
+            )
+        );
+        ensure!(
+            !args.auto_restart,
+            Failure::new(
+                Code::InvalidInput,
+                "--auto-restart can't be used without running streamlink"
+            )
+        );
+        ensure!(
+            args.launch_timeout.is_none(),
+            Failure::new(
+                Code::InvalidInput,
+                "--launch-timeout can't be used without running streamlink"
+            )
+        );
+        ensure!(
+            !args.multi,
+            Failure::new(Code::InvalidInput, "--multi can't be used without running streamlink")
+        );
+    } else {
+        ensure!(
+            !args.json || args.print_metadata,
+            Failure::new(
+                Code::InvalidInput,
+                "--json only works with `cbc-sl url` or --print-metadata"
+            )
+        );
+        // Without --stdout, whether it started is only known from streamlink's info log
+        let quiet = args.log_file.is_some()
+            || matches!(args.loglevel.as_str(), "none" | "error" | "warning");
+        ensure!(
+            args.launch_timeout.is_none() || args.pipe.is_some() || !quiet,
+            Failure::new(
+                Code::InvalidInput,
+                "--launch-timeout needs streamlink's log, so it can't be used with --log-file or \
+                a --loglevel below info"
+            )
+        );
+    }
+    if args.quality.is_none() {
+        parse_quality(&DEFAULT_QUALITY)
+            .map_err(|e| Failure::new(Code::InvalidInput, format!("CBC_SL_QUALITY: {e}")))?;
+    }
+    if args.audio_language.is_some() && args.picks_variant() && !args.audio_only {
+        eprintln!(
+            "--audio-language is ignored with --distrust-streamlink, --max-bandwidth or \
+            --format-sort: streamlink only gets the chosen video stream, not the audio tracks"
+        );
+    }
+    if args.multi {
+        return play_multi(agent, global, args);
+    }
+    let mut id = if args.latest {
+        pick_latest(agent, global, args)?
+    } else if let Some(index) = args.from_history {
+        history::get(index)?.id
+    } else if let (None, true) = (&args.url, global.offline) {
+        // the page comes from --page-file, so there's nothing to look up
+        "offline".to_owned()
+    } else {
+        let url = args
+            .url
+            .as_deref()
+            .ok_or_else(|| Failure::new(Code::InvalidInput, "no URL or ID given; see --help"))?;
+        parse_cbc_id(url)?
+    };
+    if let Some(media) = radiocanada::Media::parse(&id) {
+        return play_radio_canada(agent, global, args, &media, no_run);
+    }
+
+    let mut played = 0;
+    loop {
+        let state = play(agent, global, args, &id, no_run).inspect_err(|_| {
+            stream_cache::forget(&id);
+        })?;
+        played += 1;
+        if no_run || !args.autoplay || played > args.max_autoplay {
+            return Ok(());
+        }
+        let Some(next) = state.video.next_clip() else {
+            println!("No recommended clip to play next.");
+            return Ok(());
+        };
+        println!("Up next: {} - {}", next.id, next.title);
+        id = next.id.clone();
+    }
+}
+
+/// `--multi`: resolve every URL, then run a streamlink for each at once. A video that can't be
+/// resolved is skipped (unless `--fail-fast`), as long as something is left to play.
+fn play_multi(agent: &Agent, global: &Global, args: &PlayArgs) -> Result<()> {
+    let urls: Vec<_> = args.url.iter().chain(&args.more_urls).collect();
+    ensure!(
+        urls.len() >= 2,
+        Failure::new(Code::InvalidInput, "--multi needs at least two URLs or IDs")
+    );
+    ensure!(
+        urls.len() <= streamlink::MAX_RUNNING,
+        Failure::new(
+            Code::InvalidInput,
+            format!("--multi can play at most {} streams at once", streamlink::MAX_RUNNING)
+        )
+    );
+    ensure!(
+        args.player == Player::Streamlink,
+        Failure::new(Code::InvalidInput, "--multi only works with streamlink as the player")
+    );
+    if let Some(log_file) = &args.log_file {
+        prepare_log_file(log_file, args.log_append)?;
+    }
+    let mut cmds = Vec::new();
+    let mut last_error = None;
+    for (i, url) in urls.into_iter().enumerate() {
+        let label = (i + 1).to_string();
+        let resolved = parse_cbc_id(url).and_then(|id| {
+            ensure!(
+                radiocanada::Media::parse(&id).is_none(),
+                "Radio-Canada videos can't be played with --multi yet"
+            );
+            let target = player_url(&id);
+            let initial_state = load_player(agent, &target)?;
+            let source = stream_source(&initial_state.video, args)?;
+            let master_url =
+                wait_for_stream(args, "the stream", || source.master_url(agent, global))?;
+            let variant = pick_variant(agent, args, &master_url)?;
+            let stream = variant.map_or(master_url, |v| v.uri);
+            if args.save_history {
+                let entry = history::Entry::new(&id, &initial_state.video.current_clip.title);
+                if let Err(e) = history::record(&entry) {
+                    eprintln!("failed to save history: {e:#}");
+                }
+            }
+            let title = format!("[{label}] {}", initial_state.video.current_clip.title);
+            let mut cmd = streamlink_command(global, args, &target, &stream);
+            cmd.arg("--title").arg(&title);
+            cmd.arg(&stream).arg(args.streamlink_quality());
+            Ok((title, cmd))
+        });
+        match resolved {
+            Ok((title, cmd)) => {
+                eprintln!("{title}");
+                cmds.push((label, cmd));
+            }
+            Err(e) if !args.fail_fast => {
+                eprintln!("[{label}] {url}: {e:#}");
+                last_error = Some(e);
+            }
+            Err(e) => return Err(e.context(format!("[{label}] {url}"))),
+        }
+    }
+    if cmds.is_empty() {
+        return Err(last_error.unwrap().context("none of the streams could be played"));
+    }
+    let launch_timeout = args.launch_timeout.map(Duration::from_secs);
+    streamlink::run_all(cmds, launch_timeout, args.fail_fast)
+}
+
+/// Play a Radio-Canada video, or print its stream with `cbc-sl url`. There's no player page to
+/// read, so only the options that don't need the video's details work.
+fn play_radio_canada(
+    agent: &Agent,
+    global: &Global,
+    args: &PlayArgs,
+    media: &radiocanada::Media,
+    no_run: bool,
+) -> Result<()> {
+    let unsupported = [
+        (args.print_metadata, "--print-metadata"),
+        (args.show_assets, "--show-assets"),
+        (args.resolve_only, "--resolve-only"),
+        (args.json, "--json"),
+        (args.autoplay, "--autoplay"),
+        (args.shortcut.is_some(), "--shortcut"),
+        (args.output_template.is_some(), "--output-template"),
+        (args.auto_restart, "--auto-restart"),
+        (args.save_history, "--save-history"),
+        (args.cast.is_some(), "--cast"),
+        (args.player == Player::Iina, "--player iina"),
+    ];
+    if let Some((_, flag)) = unsupported.iter().find(|(used, _)| *used) {
+        bail!(Failure::new(
+            Code::InvalidInput,
+            format!("{flag} doesn't work with Radio-Canada videos yet")
+        ));
+    }
+    let json_url = media.validation_url();
+    let master_url =
+        wait_for_stream(args, "the stream", || fetch_master_url(agent, global, &json_url))?;
+    if args.list_formats {
+        let playlist = wait_for_stream(args, "the master playlist", || {
+            get_string(agent, &master_url, "master.m3u8")
+        })?;
+        return print_formats(&playlist);
+    }
+    let variant = pick_variant(agent, args, &master_url)?;
+    let stream = variant.map_or(master_url, |v| v.uri);
+    if no_run {
+        if let Some(user_agent) = global.user_agent() {
+            println!("User-Agent: {user_agent}");
+        }
+        println!("URL: {}", stream);
+        return Ok(());
+    }
+    if let Some(log_file) = &args.log_file {
+        prepare_log_file(log_file, args.log_append)?;
+    }
+    let mut cmd = streamlink_command(global, args, radiocanada::REFERER, &stream);
+    if let Some(output) = &args.output {
+        cmd.arg("--output").arg(output);
+    }
+    cmd.arg(&stream).arg(args.streamlink_quality());
+    let launch_timeout = args.launch_timeout.map(Duration::from_secs);
+    match &args.pipe {
+        Some(consumer) => streamlink::run_piped(cmd, consumer, launch_timeout),
+        None => streamlink::run(cmd, launch_timeout),
+    }
+}
+
+/// streamlink with everything but the stream and quality: log level, headers, proxy, cookies
+/// and player arguments.
+fn streamlink_command(global: &Global, args: &PlayArgs, target: &str, stream: &str) -> Command {
+    let mut cmd = Command::new(&args.streamlink);
+    cmd.arg("--loglevel").arg(&args.loglevel);
+    if global.http_debug || global.http_debug_full {
+        eprintln!("[http] headers for streamlink:");
+    }
+    for (name, value) in streamlink_headers(global, args, target) {
+        if global.http_debug || global.http_debug_full {
+            eprintln!("[http]   {name}: {value}");
+        }
+        cmd.arg("--http-header").arg(format!("{name}={value}"));
+    }
+    if let Some(log_file) = &args.log_file {
+        cmd.arg("--logfile").arg(log_file);
+    }
+    if let Some(proxy) = global.streamlink_proxy() {
+        cmd.arg("--http-proxy").arg(proxy);
+    }
+    if let Some(language) = &args.audio_language {
+        cmd.arg("--hls-audio-select").arg(language);
+    }
+    for (name, value) in cookies::jar().map(|jar| jar.matching(stream)).unwrap_or_default() {
+        cmd.arg("--http-cookie").arg(format!("{name}={value}"));
+    }
+    if !args.player_args.is_empty() {
+        let joined: Vec<_> = args.player_args.iter().map(|arg| quote_player_arg(arg)).collect();
+        cmd.arg("--player-args").arg(joined.join(" "));
+    }
+    cmd
+}
+
+/// Check the master playlist for DRM and, with `--strict-quality`, the quality asked for, then
+/// pick a variant from it if we choose rather than streamlink. The playlist is only fetched if
+/// one of those needs it. Its variants are relative to where it ended up after redirects, which
+/// is often a different CDN host.
+fn pick_variant(agent: &Agent, args: &PlayArgs, master_url: &str) -> Result<Option<Variant>> {
+    // offline, the checks are only done on a playlist that was given
+    let checks = match offline::fixtures() {
+        Some(fixtures) => fixtures.playlist.is_some(),
+        None => !args.ignore_drm || args.wait_for_stream,
+    };
+    if !args.picks_variant() && !args.strict_quality && !checks {
+        return Ok(None);
+    }
+    let (playlist, final_url) = fetch_playlist(agent, args, master_url)?;
+    if !args.ignore_drm {
+        check_drm(agent, &final_url, &playlist)?;
+    }
+    if is_media_playlist(&playlist) {
+        debug!("playlist is a media playlist, so there's no variant to pick");
+        return Ok(None);
+    }
+    if args.strict_quality {
+        check_quality(args.quality(), &playlist)?;
+    }
+    if !args.picks_variant() {
+        return Ok(None);
+    }
+    Ok(Some(choose_variant(args, &final_url, &playlist)?))
+}
+
+/// Fetch the master playlist, returning it and the URL it ended up at. With `--offline`, it's
+/// `--playlist-file`, as if it came from `master_url`.
+fn fetch_playlist(
+    agent: &Agent,
+    args: &PlayArgs,
+    master_url: &str,
+) -> Result<(String, String), CbcError> {
+    if let Some(fixtures) = offline::fixtures() {
+        let playlist = fixtures.playlist.as_deref().ok_or_else(|| CbcError::NotFound {
+            what: "no master playlist: --offline needs --playlist-file for this".to_owned(),
+        })?;
+        return Ok((playlist.to_owned(), master_url.to_owned()));
+    }
+    wait_for_stream(args, "the master playlist", || get_page(agent, master_url, "master.m3u8"))
+}
+
+/// Resolve the stream for `id` and run streamlink on it (or print it, with `--no-run`).
+/// Returns the player state, for finding what to play next.
+fn play(
+    agent: &Agent,
+    global: &Global,
+    args: &PlayArgs,
+    id: &str,
+    no_run: bool,
+) -> Result<InitialState> {
+    let target = player_url(id);
+    // --dump-state is for seeing what CBC sends, so it has to be fetched
+    let use_cache = !args.refresh
+        && !global.offline
+        && global.medianet_params.is_empty()
+        && global.dump_state.is_none();
+    let cached = if use_cache { stream_cache::get(id) } else { None };
+    let (initial_state, state_json) = match &cached {
+        Some(entry) => {
+            let state = serde_json::from_str(&entry.initial_state)
+                .map_err(|e| CbcError::parse("the cached player state", e))?;
+            (state, entry.initial_state.clone())
+        }
+        None => load_player_json(agent, &target)?,
+    };
+    if args.print_metadata {
+        print_metadata(&initial_state.video.current_clip, args.json, global.time_format)?;
+        return Ok(initial_state);
+    }
+    if args.show_assets {
+        for asset in &initial_state.video.current_clip.media.assets {
+            match asset.options.as_ref().filter(|options| !options.is_null()) {
+                Some(options) => println!("{}\t{}\t{options}", asset.asset_type, asset.key),
+                None => println!("{}\t{}", asset.asset_type, asset.key),
+            }
+        }
+        return Ok(initial_state);
+    }
+    if let Some(path) = args.shortcut.as_deref().filter(|_| !args.shortcut_stream) {
+        shortcut::write(path, &initial_state.video.current_clip.title, &target, None)?;
+        return Ok(initial_state);
+    }
+    let source = stream_source(&initial_state.video, args)?;
+    if args.resolve_only {
+        println!("{}: {}", source.asset_type(), source.key());
+        let dai = initial_state
+            .video
+            .current_clip
+            .media
+            .assets
+            .iter()
+            .find(|a| a.asset_type == DAI_ASSET && a.asset_type != source.asset_type());
+        if let Some(dai) = dai {
+            println!("{}: {}", dai.asset_type, dai.key);
+        }
+        return Ok(initial_state);
+    }
+
+    if let Some(restrictions) = initial_state.video.current_clip.media.restrictions() {
+        eprintln!("This is marked {restrictions}; if getting the stream fails, that's likely why.");
+    }
+    let master_url = match cached.filter(|entry| entry.asset_type == source.asset_type()) {
+        Some(entry) => entry.master_url,
+        None => {
+            let url = wait_for_stream(args, "the stream", || source.master_url(agent, global))?;
+            if use_cache {
+                let entry = stream_cache::Entry::new(&state_json, source.asset_type(), &url);
+                stream_cache::save(id, &entry);
+            }
+            url
+        }
+    };
+    let master_url = master_url.as_str();
+
+    if args.list_formats {
+        let (playlist, _) = fetch_playlist(agent, args, master_url)?;
+        print_formats(&playlist)?;
+        return Ok(initial_state);
+    }
+    let variant = pick_variant(agent, args, master_url)?;
+    let stream = variant.as_ref().map_or(master_url, |v| v.uri.as_str());
+    if let Some(path) = &args.shortcut {
+        let mut command = vec![args.streamlink.to_string_lossy().into_owned()];
+        for (name, value) in streamlink_headers(global, args, &target) {
+            command.extend(["--http-header".to_owned(), format!("{name}={value}")]);
+        }
+        if let Some(proxy) = global.streamlink_proxy() {
+            command.extend(["--http-proxy".to_owned(), proxy]);
+        }
+        if let Some(language) = &args.audio_language {
+            command.extend(["--hls-audio-select".to_owned(), language.clone()]);
+        }
+        command.extend([stream.to_owned(), args.streamlink_quality().to_owned()]);
+        shortcut::write(path, &initial_state.video.current_clip.title, stream, Some(&command))?;
+        return Ok(initial_state);
+    }
+    if no_run && args.json {
+        let headers = streamlink_headers(global, args, &target);
+        let header = |name: &str| {
+            headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+        };
+        let output = ResolvedStream {
+            url: stream,
+            user_agent: header("User-Agent"),
+            referer: header("Referer"),
+            title: &initial_state.video.current_clip.title,
+            show_name: initial_state.video.current_clip.show_name.as_deref(),
+            proxy: global.streamlink_proxy(),
+            quality: if variant.is_none() { Some(args.streamlink_quality()) } else { None },
+            variant: variant.as_ref(),
+        };
+        println!("{}", serde_json::to_string(&output)?);
+    } else if no_run {
+        if let Some(user_agent) = global.user_agent() {
+            println!("User-Agent: {user_agent}");
+        }
+        println!("URL: {}", stream);
+    } else {
+        if args.save_history {
+            let entry = history::Entry::new(id, &initial_state.video.current_clip.title);
+            if let Err(e) = history::record(&entry) {
+                eprintln!("failed to save history: {e:#}");
+            }
+        }
+        if let Some(device) = &args.cast {
+            cast::cast(stream, device.as_deref(), global.proxy.is_some())?;
+            return Ok(initial_state);
+        }
+        if args.player == Player::Iina {
+            let headers = streamlink_headers(global, args, &target);
+            let proxy = global.streamlink_proxy();
+            let language = args.audio_language.as_deref();
+            iina::play(stream, &headers, proxy.as_deref(), language, &args.player_args)?;
+            return Ok(initial_state);
+        }
+        if let Some(log_file) = &args.log_file {
+            prepare_log_file(log_file, args.log_append)?;
+        }
+        let streamlink_cmd = |stream: &str, output: Option<&Path>| {
+            let mut cmd = streamlink_command(global, args, &target, stream);
+            if let Some(output) = output {
+                cmd.arg("--output").arg(output);
+            }
+            cmd.arg(stream).arg(args.streamlink_quality());
+            cmd
+        };
+        let launch_timeout = args.launch_timeout.map(Duration::from_secs);
+        let output = args.output.clone().or_else(|| {
+            let template = args.output_template.as_deref()?;
+            let path = PathBuf::from(initial_state.video.current_clip.file_name(template));
+            eprintln!("Recording to {}", path.display());
+            Some(path)
+        });
+        if let Some(consumer) = &args.pipe {
+            streamlink::run_piped(streamlink_cmd(stream, None), consumer, launch_timeout)?;
+            return Ok(initial_state);
+        }
+        let restartable = output.is_some() || args.auto_restart;
+        let mut stream = stream.to_owned();
+        let mut restarts = 0;
+        loop {
+            let path = output.as_deref().map(|output| segment_path(output, restarts));
+            match streamlink::run(streamlink_cmd(&stream, path.as_deref()), launch_timeout) {
+                Ok(()) => break,
+                Err(e)
+                    if restartable
+                        && restarts < args.max_restarts
+                        && is_dropout(&e)
+                        && initial_state.video.current_clip.should_be_live() =>
+                {
+                    restarts += 1;
+                    eprintln!(
+                        "[{}] streamlink failed during a live event ({e:#}); getting a fresh \
+                        stream URL in {}s (restart {restarts} of {})",
+                        jiff::Zoned::now().strftime("%H:%M:%S"),
+                        RESTART_DELAY.as_secs(),
+                        args.max_restarts
+                    );
+                    std::thread::sleep(RESTART_DELAY);
+                    stream = source.master_url(agent, global)?;
+                    if args.picks_variant() {
+                        let (playlist, final_url) = get_page(agent, &stream, "master.m3u8")?;
+                        if !is_media_playlist(&playlist) {
+                            stream = choose_variant(args, &final_url, &playlist)?.uri;
+                        }
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+    Ok(initial_state)
+}
+
+/// Fetch the player page at `target` and get the player state out of it, checking whether it
+/// needs a CBC account.
+fn load_player(agent: &Agent, target: &str) -> Result<InitialState, CbcError> {
+    load_player_json(agent, target).map(|(state, _)| state)
+}
+
+/// [load_player], also returning the player state's JSON.
+fn load_player_json(agent: &Agent, target: &str) -> Result<(InitialState, String), CbcError> {
+    let (page, final_url) = match offline::fixtures() {
+        Some(fixtures) => (fixtures.page.clone(), target.to_owned()),
+        None => get_page(agent, target, "player-page.html")?,
+    };
+    parse_player(target, &page, &final_url)
+}
+
+/// Get the player state and its JSON out of the player page, which was requested as `target` and
+/// came from `final_url`.
+fn parse_player(
+    target: &str,
+    page: &str,
+    final_url: &str,
+) -> Result<(InitialState, String), CbcError> {
+    if final_url != target && is_login_url(final_url) {
+        debug!("login_redirect url={final_url}");
+        return Err(CbcError::RequiresAccount);
+    }
+    let Some(preload_json) = api::find_initial_state(page) else {
+        if looks_paywalled(page) {
+            return Err(CbcError::RequiresAccount);
+        }
+        let context = "couldn't find the player state on the page".to_owned();
+        return Err(CbcError::ApiChanged { context });
+    };
+    debug!("initial_state bytes={}", preload_json.len());
+    debug::dump("initial-state.json", preload_json);
+    let initial_state: InitialState = match serde_json::from_str(preload_json) {
+        Ok(state) => state,
+        Err(_) if looks_paywalled(page) => return Err(CbcError::RequiresAccount),
+        Err(e) => return Err(CbcError::parse("the player state", e)),
+    };
+    if initial_state.video.current_clip.media.assets.is_empty() && looks_paywalled(page) {
+        return Err(CbcError::RequiresAccount);
+    }
+    Ok((initial_state, preload_json.to_owned()))
+}
+
+/// For `--print-metadata`: everything we know about a clip from its player page.
+fn print_metadata(clip: &api::CurrentClip, json: bool, time_format: TimeFormat) -> Result<()> {
+    let kind = match clip.media.stream_type {
+        Some(api::StreamType::Live) => "live",
+        Some(api::StreamType::OnDemand) => "on-demand",
+        Some(api::StreamType::Unknown) | None => "unknown",
+    };
+    if json {
+        println!("{}", serde_json::to_string(&clip.metadata())?);
+        return Ok(());
+    }
+    let assets: Vec<_> = clip.media.assets.iter().map(|a| a.asset_type.as_str()).collect();
+    let pattern = format!("%Y-%m-%d {}", time_format.pattern(true));
+    let time = |t: Option<jiff::Zoned>| {
+        t.map_or("unknown".to_owned(), |t| t.strftime(&pattern).to_string())
+    };
+    let field = |label: &str, value: &str| {
+        let label = format!("{label:<12}");
+        let value = api::plain(value);
+        println!("{} {value}", label.if_supports_color(Stdout, |text| text.bold()));
+    };
+    field("Title:", &clip.title);
+    if let Some(show) = &clip.show_name {
+        field("Show:", show);
+    }
+    field("ID:", &clip.source_id);
+    field("Type:", kind);
+    field("Published:", &time(clip.published()));
+    field("Updated:", &time(clip.updated()));
+    field("Duration:", &api::format_duration(clip.media.duration as f64));
+    field("Assets:", &if assets.is_empty() { "none".to_owned() } else { assets.join(", ") });
+    if let Some(restrictions) = clip.media.restrictions() {
+        field("Restricted:", &restrictions);
+    }
+    if !clip.description.is_empty() {
+        field("Description:", &clip.description);
+    }
+    Ok(())
+}
+
+/// The asset type of Google DAI streams, which have ads stitched in.
+const DAI_ASSET: &str = "platform-dai";
+
+/// Where a video's master playlist URL comes from.
+enum Source {
+    /// An asset that gives the URL when fetched: medianet, or whatever `--asset` names
+    Asset { asset_type: String, key: String },
+    /// A DAI stream, which needs a session created for it
+    Dai { key: String, dai: dai::Dai },
+}
+
+impl Source {
+    fn asset_type(&self) -> &str {
+        match self {
+            Self::Asset { asset_type, .. } => asset_type,
+            Self::Dai { .. } => DAI_ASSET,
+        }
+    }
+
+    fn key(&self) -> &str {
+        match self {
+            Self::Asset { key, .. } | Self::Dai { key, .. } => key,
+        }
+    }
+
+    /// Get a fresh master playlist URL. These expire, so this is repeated when restarting.
+    fn master_url(&self, agent: &Agent, global: &Global) -> Result<String, CbcError> {
+        match self {
+            Self::Asset { key, .. } => fetch_master_url(agent, global, key),
+            Self::Dai { dai, .. } => dai.master_url(agent),
+        }
+    }
+}
+
+/// Which of the video's assets to play: `--asset` (medianet by default), or DAI with
+/// `--prefer-dai` or when there's no medianet asset.
+fn stream_source(video: &api::Video, args: &PlayArgs) -> Result<Source> {
+    let dai = || -> Result<Source> {
+        let asset = video.asset(DAI_ASSET)?;
+        Ok(Source::Dai { key: asset.key.clone(), dai: dai::Dai::from_asset(asset)? })
+    };
+    if args.asset == DAI_ASSET || (args.prefer_dai && video.asset(DAI_ASSET).is_ok()) {
+        return dai();
+    }
+    match video.asset(&args.asset) {
+        Ok(asset) => {
+            Ok(Source::Asset { asset_type: asset.asset_type.clone(), key: asset.key.clone() })
+        }
+        Err(e) if args.asset == "medianet" && video.asset(DAI_ASSET).is_ok() => {
+            debug!("medianet missing, using dai: {e}");
+            eprintln!("There's no medianet stream; playing the DAI one, which has ads.");
+            dai()
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Fetch the medianet JSON at `json_url` and return the master playlist URL from it. The URL
+/// has a short-lived token in it, so this is repeated when restarting a recording.
+fn fetch_master_url(agent: &Agent, global: &Global, json_url: &str) -> Result<String, CbcError> {
+    if let Some(fixtures) = offline::fixtures() {
+        let body = fixtures.stream.as_deref().ok_or_else(|| CbcError::NotFound {
+            what: "no stream JSON: --offline needs --stream-file for this".to_owned(),
+        })?;
+        return parse_medianet(200, "application/json", body);
+    }
+    let json_url = &with_query_params(json_url, &global.medianet_params);
+    match fetch_medianet(agent, json_url, None) {
+        // member-only streams are refused like geo-blocked ones, so try again signed in
+        Err(e @ (CbcError::GeoBlocked { .. } | CbcError::RequiresAccount)) => {
+            match account::claims_token(agent) {
+                Ok(Some(claims)) => {
+                    debug!("medianet retrying signed in");
+                    fetch_medianet(agent, json_url, Some(claims))
+                }
+                Ok(None) => Err(e),
+                Err(account_error) => {
+                    eprintln!("couldn't use the saved CBC sign-in: {account_error:#}");
+                    Err(e)
+                }
+            }
+        }
+        result => result,
+    }
+}
+
+/// `url` with `params` added to its query, replacing any already there with the same name.
+fn with_query_params(url: &str, params: &[(String, String)]) -> String {
+    if params.is_empty() {
+        return url.to_owned();
+    }
+    let Ok(mut parsed) = Url::parse(url) else { return url.to_owned() };
+    let kept: Vec<(String, String)> = parsed
+        .query_pairs()
+        .filter(|(name, _)| params.iter().all(|(param, _)| param != name))
+        .map(|(name, value)| (name.into_owned(), value.into_owned()))
+        .collect();
+    parsed.query_pairs_mut().clear().extend_pairs(kept).extend_pairs(params);
+    debug!("medianet params added url={parsed}");
+    parsed.into()
+}
+
+/// One request to medianet for the stream URL, with the account's claims token if given.
+fn fetch_medianet(agent: &Agent, json_url: &str, claims: Option<&str>) -> Result<String, CbcError> {
+    debug!("request method=GET url={json_url}");
+    let mut request = agent.get(json_url);
+    if let Some(claims) = claims {
+        request = request.set("x-claims-token", claims);
+    }
+    // Error statuses still have a body worth reporting
+    let resp = match retry::call(request, None).map_err(|e| *e) {
+        Ok(resp) | Err(ureq::Error::Status(_, resp)) => resp,
+        Err(e) => return Err(e.into()),
+    };
+    let status = resp.status();
+    if status == 404 {
+        let what = "the stream isn't published yet (HTTP 404)".to_owned();
+        return Err(CbcError::NotFound { what });
+    }
+    let content_type = resp.content_type().to_owned();
+    let body = resp.into_string()?;
+    debug!("response status={status} type={content_type} bytes={} url={json_url}", body.len());
+    debug::dump("stream.json", &body);
+    parse_medianet(status, &content_type, &body)
+}
+
+/// Get the master playlist URL out of a medianet response.
+fn parse_medianet(status: u16, content_type: &str, body: &str) -> Result<String, CbcError> {
+    match Stream::from_response(status, content_type, body) {
+        Ok(stream) => Ok(stream.url),
+        Err(CbcError::ApiChanged { .. }) if matches!(status, 403 | 451) => {
+            Err(CbcError::GeoBlocked { reason: format!("HTTP {status}") })
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Run `fetch`, and with `--wait-for-stream`, keep retrying it while it fails because `what`
+/// hasn't been published yet. Other errors, like network failures, aren't retried.
+fn wait_for_stream<T>(
+    args: &PlayArgs,
+    what: &str,
+    mut fetch: impl FnMut() -> Result<T, CbcError>,
+) -> Result<T, CbcError> {
+    let mut attempt = 1;
+    loop {
+        match fetch() {
+            // offline, the files won't change by waiting
+            Err(e)
+                if args.wait_for_stream
+                    && attempt < args.wait_attempts
+                    && e.is_not_ready()
+                    && offline::fixtures().is_none() =>
+            {
+                debug!("not_ready what={what} attempt={attempt} error={e:#}");
+                eprintln!(
+                    "{what} isn't ready yet; trying again in {}s ({attempt}/{})",
+                    args.wait_interval, args.wait_attempts
+                );
+                std::thread::sleep(Duration::from_secs(args.wait_interval));
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Pick the variant to play ourselves, for `--distrust-streamlink` and `--max-bandwidth`.
+/// `--quality` only applies to `--distrust-streamlink`; the others conflict with it, but
+/// `$CBC_SL_QUALITY` may still be set.
+fn choose_variant(args: &PlayArgs, master_url: &str, playlist: &str) -> Result<Variant, CbcError> {
+    if args.audio_only {
+        return get_audio_stream(master_url, playlist, args.audio_language.as_deref());
+    }
+    let quality = (args.max_bandwidth.is_none() && args.format_sort.is_none())
+        .then(|| Quality::parse(args.quality()));
+    get_best_stream(
+        master_url,
+        playlist,
+        args.max_bandwidth,
+        args.format_sort.as_ref(),
+        quality.as_ref(),
+    )
+}
+
+/// How long to wait before restarting streamlink, so a stream that's briefly down can recover.
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+/// Whether streamlink stopped on its own, as opposed to not being found or the user quitting
+/// (streamlink exits with 130 on Ctrl-C).
+fn is_dropout(e: &anyhow::Error) -> bool {
+    match e.downcast_ref::<CbcError>() {
+        Some(CbcError::StreamlinkFailed { code, .. }) => *code != Some(130),
+        Some(CbcError::StreamlinkStalled { .. }) => true,
+        _ => false,
+    }
+}
+
+/// Where a recording goes after `restarts` restarts: `--output` itself at first, then e.g.
+/// `event.1.ts`, `event.2.ts` so nothing already written is overwritten.
+fn segment_path(output: &Path, restarts: u32) -> PathBuf {
+    if restarts == 0 {
+        return output.to_owned();
+    }
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match output.extension() {
+        Some(ext) => format!("{stem}.{restarts}.{}", ext.to_string_lossy()),
+        None => format!("{stem}.{restarts}"),
+    };
+    output.with_file_name(name)
+}
+
+/// Quote an argument for streamlink's `--player-args`, which it splits like a shell would.
+/// Double quotes work for this on every platform.
+fn quote_player_arg(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains(|c: char| c.is_whitespace() || "\"'\\".contains(c)) {
+        return arg.to_owned();
+    }
+    let escaped = arg.replace('\\', "\\\\").replace('"', "\\\"");
+    format!("\"{escaped}\"")
+}
+
+/// Adds the non-empty `--header`s to every request we make.
+struct ExtraHeaders(Vec<(String, String)>);
+
+impl Middleware for ExtraHeaders {
+    fn handle(&self, mut request: Request, next: MiddlewareNext) -> Result<Response, ureq::Error> {
+        for (name, value) in self.0.iter().filter(|(_, value)| !value.is_empty()) {
+            request = request.set(name, value);
+        }
+        next.handle(request)
+    }
+}
+
+/// The headers to pass to streamlink: our User-Agent and the player page as Referer, overridden
+/// by any `--header`s. A header given with an empty value removes it.
+fn streamlink_headers(global: &Global, args: &PlayArgs, referer: &str) -> Vec<(String, String)> {
+    let mut headers = Vec::new();
+    if let Some(user_agent) = global.user_agent() {
+        headers.push(("User-Agent".to_owned(), user_agent.to_owned()));
+    }
+    if !args.referer_none && !global.no_referer {
+        headers.push(("Referer".to_owned(), referer.to_owned()));
+    }
+    for (name, value) in global.extra_headers() {
+        headers.retain(|(n, _)| !n.eq_ignore_ascii_case(&name));
+        if !value.is_empty() {
+            headers.push((name, value));
+        }
+    }
+    headers
+}
+
+fn is_user_agent(name: &str) -> bool {
+    name.eq_ignore_ascii_case("User-Agent")
+}
+
+/// Checks that each of the comma-separated qualities is something streamlink could plausibly
+/// offer, so typos like `720` fail before any scraping.
+fn parse_quality(input: &str) -> std::result::Result<String, String> {
+    const KEYWORDS: &[&str] =
+        &["best", "worst", "best-unfiltered", "worst-unfiltered", "audio", "audio_only"];
+    let resolution = regex!(r#"^[[:digit:]]+p(?:[[:digit:]]+)?(?:_alt[[:digit:]]*)?$"#);
+    for quality in input.split(',').map(str::trim) {
+        if !KEYWORDS.contains(&quality) && !resolution.is_match(quality) {
+            return Err(format!(
+                "'{quality}' isn't a quality; use best, worst, audio, or a resolution like 720p"
+            ));
+        }
+    }
+    Ok(input.to_owned())
+}
+
+/// For `--strict-quality`: fail if none of the requested resolutions are in the master playlist.
+/// Keywords like `best` always match.
+fn check_quality(quality: &str, mp: &str) -> Result<()> {
+    let available = quality_names(mp)?;
+    let is_resolution = |q: &str| q.starts_with(|c: char| c.is_ascii_digit());
+    ensure!(
+        quality
+            .split(',')
+            .map(str::trim)
+            .any(|q| !is_resolution(q) || available.iter().any(|a| a == q)),
+        "quality {quality} isn't available; this stream has {}",
+        available.join(", ")
+    );
+    Ok(())
+}
+
+/// For `--list-formats`: one line per variant, highest bandwidth first.
+fn print_formats(mp: &str) -> Result<()> {
+    let mp = MasterPlaylist::try_from(mp)?;
+    let mut variants: Vec<_> = mp
+        .variant_streams
+        .iter()
+        .filter(|v| matches!(v, VariantStream::ExtXStreamInf { .. }))
+        .collect();
+    ensure!(!variants.is_empty(), Failure::new(Code::NoStreams, "no streams found"));
+    variants.sort_by_key(|v| std::cmp::Reverse(v.bandwidth()));
+    println!("{:<11} {:>10} {:>5} CODECS", "RESOLUTION", "BANDWIDTH", "FPS");
+    for v in variants {
+        let resolution = match v.resolution() {
+            Some((width, height)) => format!("{width}x{height}"),
+            None if v.is_audio_only() => "audio".to_owned(),
+            None => "?".to_owned(),
+        };
+        let fps = v.frame_rate().map_or(String::new(), |fps| format!("{fps:.0}"));
+        let codecs = v.codecs().unwrap_or_default();
+        println!("{resolution:<11} {:>9}k {fps:>5} {codecs}", v.bandwidth() / 1000);
+    }
+    let audio: Vec<_> = mp.media.iter().filter(|m| m.media_type == MediaType::Audio).collect();
+    if !audio.is_empty() {
+        println!();
+        println!("{:<9} {:<8} NAME", "LANGUAGE", "DEFAULT");
+        for m in audio {
+            let default = if m.is_default { "yes" } else { "" };
+            println!("{:<9} {default:<8} {}", m.language().map_or("?", |code| code), m.name());
+        }
+    }
+    Ok(())
+}
+
+/// Streamlink-style names (`720p`, `1080p60`) of the variants in a master playlist.
+fn quality_names(mp: &str) -> Result<Vec<String>> {
+    let mp = MasterPlaylist::try_from(mp)?;
+    let mut names: Vec<String> =
+        mp.variant_streams.iter().filter_map(|v| v.quality_name()).collect();
+    names.dedup();
+    Ok(names)
+}
+
+/// Parses `Name: Value` for `--header`. The value may be empty.
+fn parse_header(input: &str) -> std::result::Result<(String, String), String> {
+    let (name, value) = input.split_once(':').ok_or("expected 'Name: Value'")?;
+    let name = name.trim();
+    if name.is_empty() || name.contains(|c: char| c.is_whitespace() || c == '=') {
+        return Err(format!("invalid header name '{name}'"));
+    }
+    if name.eq_ignore_ascii_case("Host") {
+        return Err("Host can't be overridden".to_owned());
+    }
+    Ok((name.to_owned(), value.trim().to_owned()))
+}
+
+/// Parses `key=value` for `--medianet-param`. The value may be empty.
+fn parse_query_param(input: &str) -> std::result::Result<(String, String), String> {
+    let (key, value) = input.split_once('=').ok_or("expected 'key=value'")?;
+    if key.is_empty() {
+        return Err("the key can't be empty".to_owned());
+    }
+    Ok((key.to_owned(), value.to_owned()))
+}
+
+/// Create the log file's parent directories, and truncate it unless appending. Streamlink itself
+/// always appends.
+fn prepare_log_file(path: &Path, append: bool) -> Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("creating log directory {}", parent.display()))?;
+    }
+    if !append {
+        File::create(path).with_context(|| format!("creating log file {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Fail early if the stream is DRM-protected, since streamlink can't play it and only fails after
+/// a long delay with an unhelpful error. Keys normally live in the media playlists, so if the
+/// master playlist doesn't declare any, the best variant is checked too, if it can be fetched.
+fn check_drm(agent: &Agent, url: &str, mp: &str) -> Result<()> {
+    let mut method = key_method(mp);
+    // offline, there's only the master playlist to go on
+    if method.is_none() && offline::fixtures().is_none() {
+        if let Ok(variant) = get_best_stream(url, mp, None, None, None) {
+            // this check is only a courtesy, so failing to make it shouldn't stop playback
+            match get_string(agent, &variant.uri, "media.m3u8") {
+                Ok(media) => method = key_method(&media),
+                Err(e) => debug!("drm media_playlist_failed url={} error={e:#}", variant.uri),
+            }
+        }
+    }
+    if let Some(method) = method {
+        debug!("drm method={method}");
+        bail!(
+            "this stream is {} (METHOD={method}) and can't be played; \
+            use --ignore-drm to try anyway",
+            "DRM-protected".if_supports_color(Stdout, |text| text.bright_red().on_black()),
+        );
+    }
+    Ok(())
+}
+
+/// Returns the encryption method of the first `EXT-X-KEY` or `EXT-X-SESSION-KEY` tag in the
+/// playlist that isn't `METHOD=NONE`.
+fn key_method(playlist: &str) -> Option<String> {
+    let key_regex = regex!(r#"(?m)^#EXT-X-(?:SESSION-)?KEY:.*?METHOD=([^,\s]+)"#);
+    key_regex
+        .captures_iter(playlist)
+        .map(|cap| cap.get(1).unwrap().as_str())
+        .find(|method| *method != "NONE")
+        .map(str::to_owned)
+}
+
+/// What `cbc-sl url --json` prints. The field names are a stable contract: add fields, but
+/// don't rename or remove them.
+#[derive(Debug, Serialize)]
+struct ResolvedStream<'a> {
+    url: &'a str,
+    user_agent: Option<&'a str>,
+    referer: Option<&'a str>,
+    title: &'a str,
+    show_name: Option<&'a str>,
+    proxy: Option<String>,
+    /// The quality streamlink is given, when there isn't a variant we picked ourselves
+    quality: Option<&'a str>,
+    variant: Option<&'a Variant>,
+}
+
+/// A stream picked from a master playlist.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct Variant {
+    uri: String,
+    bandwidth: u64,
+}
+
+/// Given the URL of the master playlist, and its contents, get the highest-bandwidth stream (or
+/// the one `quality` asks for) and build an absolute URL to it.
+///
+/// Workaround for https://github.com/streamlink/streamlink/issues/4329
+fn get_best_stream(
+    url: &str,
+    mp: &str,
+    max_bandwidth: Option<u64>,
+    format_sort: Option<&FormatSort>,
+    quality: Option<&Quality>,
+) -> Result<Variant, CbcError> {
+    resolve_variant(url, parse_master_playlist(mp, max_bandwidth, format_sort, quality)?)
+}
+
+/// Like [get_best_stream], but for `--audio-only`.
+fn get_audio_stream(url: &str, mp: &str, language: Option<&str>) -> Result<Variant, CbcError> {
+    resolve_variant(url, parse_audio_rendition(mp, language)?)
+}
+
+/// Make the variant's URI absolute, relative to the master playlist's URL. That should be the
+/// URL it was fetched from after any redirects.
+fn resolve_variant(url: &str, mut variant: Variant) -> Result<Variant, CbcError> {
+    let url = Url::parse(url).map_err(|e| CbcError::parse("the master playlist URL", e))?;
+    let resolved = url.join(&variant.uri).map_err(|e| CbcError::parse("a variant's URI", e))?;
+    variant.uri = resolved.into();
+    Ok(variant)
+}
+
+/// Whether medianet gave us a media playlist (a single rendition's segments) rather than a
+/// master playlist, which happens now and then. It can be played as it is.
+fn is_media_playlist(playlist: &str) -> bool {
+    MasterPlaylist::try_from(playlist).is_err() && MediaPlaylist::try_from(playlist).is_ok()
+}
+
+/// Parse a master playlist, return the stream with the highest bandwidth, not counting any over
+/// `max_bandwidth`. If they're all over it, the lowest is used. With `format_sort`, the best
+/// stream by that order is used instead of the highest bandwidth, and with `quality`, the first
+/// of its qualities that's there.
+fn parse_master_playlist(
+    input: &str,
+    max_bandwidth: Option<u64>,
+    format_sort: Option<&FormatSort>,
+    quality: Option<&Quality>,
+) -> Result<Variant, CbcError> {
+    let mp = MasterPlaylist::try_from(input).map_err(|e| {
+        CbcError::parse("the master playlist, which isn't a media playlist either", e)
+    })?;
+    let mut variant = mp.variant_streams;
+    // streamlink ignores I-frame-only streams, which are too low a bitrate to be worth watching
+    // anyway, and would otherwise be what a tight --max-bandwidth picks
+    variant.retain(|v| matches!(v, VariantStream::ExtXStreamInf { .. }));
+    if variant.is_empty() {
+        return Err(CbcError::NotFound { what: "no streams found".to_owned() });
+    }
+    variant.sort_by(compare_variants);
+    let lowest = variant.first().unwrap().bandwidth();
+    if let Some(max) = max_bandwidth {
+        if lowest > max {
+            eprintln!("Every stream is over {max} bits/s; using the lowest, {lowest} bits/s.");
+            variant.retain(|v| v.bandwidth() == lowest);
+        } else {
+            variant.retain(|v| v.bandwidth() <= max);
+        }
+    }
+    if let Some(quality) = quality {
+        let picked = quality.pick(&variant).map_err(|what| CbcError::NotFound { what })?;
+        debug!("variant chosen bandwidth={} uri={}", picked.bandwidth(), picked.uri());
+        log_tie(&variant, picked);
+        return Ok(Variant { uri: picked.uri(), bandwidth: picked.bandwidth() });
+    }
+    // best first; sorting is stable, so --format-sort ties keep this order
+    variant.reverse();
+    if let Some(format_sort) = format_sort {
+        variant.sort_by(|a, b| format_sort.compare(a, b));
+    }
+    let best = variant.first().unwrap();
+    debug!("variant chosen bandwidth={} uri={}", best.bandwidth(), best.uri());
+    log_tie(&variant, best);
+    Ok(Variant { uri: best.uri(), bandwidth: best.bandwidth() })
+}
+
+/// Order variants from least to most preferred: by bandwidth, then resolution, then frame
+/// rate, so variants with the same bandwidth are picked the same way whatever order the
+/// playlist lists them in. Identical ones fall back to the URI, the earliest winning.
+fn compare_variants(a: &VariantStream<'_>, b: &VariantStream<'_>) -> std::cmp::Ordering {
+    a.bandwidth()
+        .cmp(&b.bandwidth())
+        .then_with(|| tie_break(a, b).map_or(std::cmp::Ordering::Equal, |(ordering, _)| ordering))
+}
+
+/// How two variants with the same bandwidth compare, and on what: the first of resolution,
+/// frame rate and URI that differs. `None` if they're the same stream.
+fn tie_break(
+    a: &VariantStream<'_>,
+    b: &VariantStream<'_>,
+) -> Option<(std::cmp::Ordering, &'static str)> {
+    let pixels = |v: &VariantStream<'_>| v.resolution().map(|(w, h)| (h, w));
+    let fps = |v: &VariantStream<'_>| v.frame_rate().unwrap_or(0.0);
+    [
+        (pixels(a).cmp(&pixels(b)), "resolution"),
+        (fps(a).total_cmp(&fps(b)), "frame rate"),
+        (b.uri().cmp(&a.uri()), "uri"),
+    ]
+    .into_iter()
+    .find(|(ordering, _)| ordering.is_ne())
+}
+
+/// Under `--debug`, say what decided between `chosen` and another variant with the same
+/// bandwidth, if there was one.
+fn log_tie(variants: &[VariantStream<'_>], chosen: &VariantStream<'_>) {
+    let others = variants.iter().filter(|v| v.bandwidth() == chosen.bandwidth());
+    for other in others {
+        if let Some((std::cmp::Ordering::Greater, reason)) = tie_break(chosen, other) {
+            debug!(
+                "variant tie bandwidth={} won_by={reason} over={}",
+                chosen.bandwidth(),
+                other.uri()
+            );
+        }
+    }
+}
+
+/// Parse a master playlist, return an audio-only rendition: the default `EXT-X-MEDIA` audio
+/// rendition if there is one, otherwise the lowest-bandwidth variant with only audio codecs.
+/// If there's nothing audio-only, falls back to the lowest-bandwidth variant.
+fn parse_audio_rendition(input: &str, language: Option<&str>) -> Result<Variant, CbcError> {
+    let mp =
+        MasterPlaylist::try_from(input).map_err(|e| CbcError::parse("the master playlist", e))?;
+    let mut media: Vec<_> =
+        mp.media.iter().filter(|m| m.media_type == MediaType::Audio && m.uri().is_some()).collect();
+    media.sort_by_key(|m| {
+        let wrong_language = language.is_some_and(|language| !is_language(m, language));
+        (wrong_language, !m.is_default)
+    });
+    if let Some(m) = media.first() {
+        debug!("audio rendition chosen group={} uri={:?}", m.group_id(), m.uri());
+        return Ok(Variant { uri: m.uri().unwrap().to_string(), bandwidth: 0 });
+    }
+    let mut variant = mp.variant_streams;
+    if variant.is_empty() {
+        return Err(CbcError::NotFound { what: "no streams found".to_owned() });
+    }
+    variant.sort_by_key(|v| v.bandwidth());
+    let audio_only = variant.iter().find(|v| v.is_audio_only());
+    let chosen = audio_only.unwrap_or_else(|| {
+        eprintln!("No audio-only stream found, using the lowest quality stream instead.");
+        variant.first().unwrap()
+    });
+    debug!("variant chosen bandwidth={} uri={}", chosen.bandwidth(), chosen.uri());
+    Ok(Variant { uri: chosen.uri(), bandwidth: chosen.bandwidth() })
+}
+
+/// Whether an audio rendition is in `language`, matched like streamlink's `--hls-audio-select`:
+/// by language code, ignoring any region (`fr` matches `fr-CA`), or by name.
+fn is_language(media: &ExtXMedia<'_>, language: &str) -> bool {
+    let matches = |text: &str| text.eq_ignore_ascii_case(language);
+    let code = media.language().map(|code| &**code).unwrap_or_default();
+    matches(code) || code.split('-').next().is_some_and(matches) || matches(media.name())
+}
+
+#[ext(name = VariantStreamExt)]
+impl VariantStream<'_> {
+    /// Whether the variant declares codecs, and they're all audio codecs.
+    fn is_audio_only(&self) -> bool {
+        const AUDIO: &[&str] = &["mp4a", "ac-3", "ec-3", "opus", "mp3"];
+        self.codecs().is_some_and(|codecs| {
+            !codecs.is_empty()
+                && codecs.split(',').all(|c| AUDIO.iter().any(|a| c.trim().starts_with(a)))
+        })
+    }
+
+    fn uri(&self) -> String {
+        match self {
+            Self::ExtXStreamInf { uri, .. } | Self::ExtXIFrame { uri, .. } => uri.to_string(),
+        }
+    }
+
+    /// Width and height in pixels, if the playlist gives them.
+    fn resolution(&self) -> Option<(u32, u32)> {
+        let resolution = StreamData::resolution(self)?;
+        Some((resolution.width() as u32, resolution.height() as u32))
+    }
+
+    /// The codecs as written in the playlist, e.g. `avc1.64001f,mp4a.40.2`.
+    fn codecs(&self) -> Option<String> {
+        StreamData::codecs(self).map(ToString::to_string)
+    }
+
+    /// The name streamlink gives the variant, like `720p` or `1080p60`. I-frame streams, which
+    /// streamlink ignores, and variants without a resolution have none.
+    fn quality_name(&self) -> Option<String> {
+        let Self::ExtXStreamInf { .. } = self else { return None };
+        let (_, height) = self.resolution()?;
+        let fps = self.frame_rate().unwrap_or(0.0).round() as u32;
+        Some(if fps > 30 { format!("{height}p{fps}") } else { format!("{height}p") })
+    }
+
+    /// The frame rate, if the playlist gives one. I-frame streams never have one.
+    fn frame_rate(&self) -> Option<f32> {
+        match self {
+            Self::ExtXStreamInf { frame_rate, .. } => frame_rate.map(|f| f.as_f32()),
+            Self::ExtXIFrame { .. } => None,
+        }
+    }
+}
+
+/// Rewrites proxy specifications:
+/// * SOCKS4 is changed to specify remote or local DNS, as `dns` says
+/// * SOCKS5 strips the `h` if present, since ureq always does remote DNS and can't handle `SOCKS5H`
+/// * Missing scheme becomes` socks5://`
+fn proxy_url_ureq(spec: &str, dns: Dns) -> String {
+    // We may need remote DNS to avoid geoblocking (ureq always does remote DNS with SOCKS5)
+    rewrite_proxy_scheme(spec, "socks5", |scheme| match (scheme, dns) {
+        ("socks5h", _) => "socks5",
+        ("socks4", Dns::Remote) => "socks4a",
+        ("socks4a", Dns::Local) => "socks4",
+        (other, _) => other,
+    })
+}
+
+/// Who resolves hostnames when connecting through a SOCKS proxy.
+#[derive(Copy, Debug, Clone, PartialEq, Eq, ValueEnum)]
+enum Dns {
+    /// The proxy, so CBC sees lookups from the proxy's side of the border
+    Remote,
+    /// This computer, e.g. on a split-horizon network
+    Local,
+}
+
+/// Stand-in host for IPv6 proxies, which ureq can't parse, resolved by [with_ureq_proxy].
+const IPV6_PROXY_HOST: &str = "ipv6-proxy.invalid";
+
+/// Configure ureq to use the proxy. ureq splits the proxy host on `:`, so a bracketed IPv6
+/// literal is swapped for a placeholder host that `resolver` maps back to the address.
+fn with_ureq_proxy(
+    ab: AgentBuilder,
+    spec: &str,
+    dns: Dns,
+    resolver: &mut Resolver,
+) -> Result<AgentBuilder> {
+    let spec = proxy_url_ureq(spec, dns);
+    if dns == Dns::Local && spec.starts_with("socks5") {
+        debug!("dns=local ignored: ureq always does remote DNS through SOCKS5");
+    }
+    let mut url = Url::parse(&spec).with_context(|| format!("invalid proxy {spec}"))?;
+    let Some(url::Host::Ipv6(ip)) = url.host() else {
+        return Ok(ab.proxy(Proxy::new(&spec)?));
+    };
+    url.set_host(Some(IPV6_PROXY_HOST)).map_err(|e| anyhow!("invalid proxy {spec}: {e}"))?;
+    debug!("ipv6_proxy ip={ip}");
+    resolver.ipv6_proxy = Some(ip);
+    Ok(ab.proxy(Proxy::new(url.as_str())?))
+}
+
+/// Resolves the hosts we connect to directly: CBC, or the proxy if there is one (SOCKS proxies
+/// look up CBC's address themselves).
+#[derive(Debug, Default)]
+struct Resolver {
+    /// The address behind [IPV6_PROXY_HOST].
+    ipv6_proxy: Option<Ipv6Addr>,
+    /// Try addresses of this family first, from `--prefer-ipv4`/`--prefer-ipv6`.
+    prefer: Option<IpFamily>,
+}
+
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+enum IpFamily {
+    V4,
+    V6,
+}
+
+impl ureq::Resolver for Resolver {
+    fn resolve(&self, netloc: &str) -> io::Result<Vec<SocketAddr>> {
+        let proxy_port =
+            netloc.strip_prefix(IPV6_PROXY_HOST).and_then(|rest| rest.strip_prefix(':'));
+        if let (Some(ip), Some(port)) = (self.ipv6_proxy, proxy_port) {
+            let port = port.parse().map_err(|_| io::Error::other("bad proxy port"))?;
+            return Ok(vec![SocketAddr::new(ip.into(), port)]);
+        }
+        let mut addrs: Vec<SocketAddr> = netloc.to_socket_addrs()?.collect();
+        if let Some(prefer) = self.prefer {
+            // stable, so the system's order is kept within each family
+            addrs.sort_by_key(|addr| addr.is_ipv4() != (prefer == IpFamily::V4));
+            debug!("resolved netloc={netloc} addrs={addrs:?}");
+        }
+        Ok(addrs)
+    }
+}
+
+/// Rewrites proxy specifications:
+/// * SOCKS4/5 is changed to specify remote or local DNS, as `dns` says
+/// * Missing scheme becomes `socks5h://`, or `socks5://` for local DNS
+fn proxy_url_streamlink(spec: &str, dns: Dns) -> String {
+    match dns {
+        Dns::Remote => rewrite_proxy_scheme(spec, "socks5h", |scheme| match scheme {
+            "socks5" => "socks5h",
+            "socks4" => "socks4a",
+            other => other,
+        }),
+        Dns::Local => rewrite_proxy_scheme(spec, "socks5", |scheme| match scheme {
+            "socks5h" => "socks5",
+            "socks4a" => "socks4",
+            other => other,
+        }),
+    }
+}
+
+/// Apply `rewrite` to the scheme of a proxy spec, or prefix `default` if it has none. Only the
+/// scheme is touched, so hosts like `socks5h.example` are left alone.
+fn rewrite_proxy_scheme(spec: &str, default: &str, rewrite: impl Fn(&str) -> &str) -> String {
+    if !spec.contains("://") {
+        return format!("{default}://{spec}");
+    }
+    match Url::parse(spec) {
+        // The scheme is always a prefix of the input, just possibly in a different case
+        Ok(url) => format!("{}{}", rewrite(url.scheme()), &spec[url.scheme().len()..]),
+        // Leave it for ureq/streamlink to complain about
+        Err(_) => spec.to_owned(),
+    }
+}
+
+/// Returns OK if the input is either numeric (ID) or a full CBC URL.
+fn probably_cbc(input: &str) -> std::result::Result<String, String> {
+    parse_cbc_id(input).map_err(|e| format!("invalid url: {e}"))
+}
+
+/// Whether a redirect took us to a sign-in or subscription page rather than the player.
+fn is_login_url(url: &str) -> bool {
+    let Ok(url) = Url::parse(url) else { return false };
+    let host = url.host_str().unwrap_or_default();
+    let path = url.path().to_ascii_lowercase();
+    host.starts_with("login.")
+        || ["login", "signin", "sign-in", "account", "subscribe", "premium"]
+            .iter()
+            .any(|marker| path.contains(marker))
+}
+
+/// Whether a player page that didn't have a usable video looks like a sign-in wall or premium
+/// upsell. Only checked after something has already gone wrong, so it can be loose.
+fn looks_paywalled(page: &str) -> bool {
+    let page = page.to_ascii_lowercase();
+    ["\"ispremium\":true", "\"requireslogin\":true", "sign in to watch", "log in to watch"]
+        .iter()
+        .any(|marker| page.contains(marker))
+        || (page.contains("premium") && page.contains("subscribe"))
+}
+
+/// Extract the ID from a bare ID, a player URL, a share link (`cbc.ca/player/ID`) or a CBC app
+/// deep link (`cbcnews://video/ID`). URLs may be missing the scheme, use `http`, the `m.cbc.ca`
+/// host, or have a query string, fragment or trailing slash. The ID must be the last path
+/// segment, so nothing ID-shaped is picked up from tracking parameters.
+fn parse_cbc_id(input: &str) -> Result<String> {
+    let input = input.trim();
+    if ID_REGEX.is_match(input) {
+        return Ok(input.to_owned());
+    }
+    if let Some(media) = radiocanada::Media::parse(input) {
+        return Ok(media.to_id());
+    }
+    let url = if input.contains("://") {
+        Url::parse(input)
+    } else {
+        Url::parse(&format!("https://{input}"))
+    }?;
+    if url.scheme().starts_with("cbc") {
+        // the apps' links vary, but end in the ID; only the modern form is used there
+        let last = url.path_segments().and_then(|mut s| s.rfind(|s| !s.is_empty()));
+        return match last.or(url.host_str()) {
+            Some(id) if id.contains('.') && ID_REGEX.is_match(id) => Ok(id.to_owned()),
+            _ => bail!("no video ID in app link"),
+        };
+    }
+    ensure!(matches!(url.scheme(), "http" | "https"), "not a web URL");
+    ensure!(
+        matches!(url.host_str(), Some("www.cbc.ca" | "cbc.ca" | "m.cbc.ca")),
+        "not a CBC.ca URL"
+    );
+    let segments: Vec<&str> =
+        url.path_segments().into_iter().flatten().filter(|s| !s.is_empty()).collect();
+    match segments.as_slice() {
+        ["player", id] | ["player", "play", id] | ["player", "play", "video", id]
+            if ID_REGEX.is_match(id) =>
+        {
+            Ok((*id).to_owned())
+        }
+        _ => bail!("no video ID in URL"),
+    }
+}
+
+/// The player page for an ID. Legacy numeric IDs live under a different path.
+fn player_url(id: &str) -> String {
+    if id.contains('.') {
+        format!("https://www.cbc.ca/player/play/video/{id}")
+    } else {
+        format!("https://www.cbc.ca/player/play/{id}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MASTER: &str = "#EXTM3U
+#EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=90000,RESOLUTION=640x360,URI=\"iframe/index.m3u8\"
+#EXT-X-STREAM-INF:BANDWIDTH=1000000,RESOLUTION=640x360
+360/index.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=3000000,RESOLUTION=1280x720
+720/index.m3u8
+";
+
+    const MEDIA: &str = "#EXTM3U
+#EXT-X-VERSION:3
+#EXT-X-TARGETDURATION:6
+#EXT-X-MEDIA-SEQUENCE:100
+#EXTINF:6.000,
+segment100.ts
+#EXTINF:6.000,
+segment101.ts
+";
+
+    #[test]
+    fn media_playlist_detected() {
+        assert!(is_media_playlist(MEDIA));
+        assert!(!is_media_playlist(MASTER));
+        assert!(!is_media_playlist("<html>not a playlist</html>"));
+        let error = parse_master_playlist(MEDIA, None, None, None).unwrap_err();
+        assert!(matches!(error, CbcError::Parse { .. }), "{error:?}");
+    }
+
+    #[test]
+    fn max_bandwidth_skips_iframe_streams() {
+        let picked = parse_master_playlist(MASTER, Some(2_000_000), None, None).unwrap();
+        assert_eq!(picked.uri, "360/index.m3u8");
+        // over the cap everywhere: the lowest real stream, not the I-frame one
+        let picked = parse_master_playlist(MASTER, Some(100_000), None, None).unwrap();
+        assert_eq!(picked.uri, "360/index.m3u8");
+    }
+
+    #[test]
+    fn bandwidth_ties_picked_the_same_either_way() {
+        let master = |first: &str, second: &str| format!("#EXTM3U\n{first}\n{second}\n");
+        let pick = |playlist: &str| parse_master_playlist(playlist, None, None, None).unwrap().uri;
+        let cases = [
+            (
+                "#EXT-X-STREAM-INF:BANDWIDTH=3000000,RESOLUTION=1280x720,FRAME-RATE=30.000\nhd.m3u8",
+                "#EXT-X-STREAM-INF:BANDWIDTH=3000000,RESOLUTION=960x540,FRAME-RATE=30.000\nsd.m3u8",
+                "hd.m3u8",
+            ),
+            (
+                "#EXT-X-STREAM-INF:BANDWIDTH=3000000,RESOLUTION=1280x720,FRAME-RATE=60.000\n60.m3u8",
+                "#EXT-X-STREAM-INF:BANDWIDTH=3000000,RESOLUTION=1280x720,FRAME-RATE=30.000\n30.m3u8",
+                "60.m3u8",
+            ),
+            (
+                "#EXT-X-STREAM-INF:BANDWIDTH=3000000,RESOLUTION=1280x720\na/index.m3u8",
+                "#EXT-X-STREAM-INF:BANDWIDTH=3000000,RESOLUTION=1280x720\nb/index.m3u8",
+                "a/index.m3u8",
+            ),
+        ];
+        for (one, other, expected) in cases {
+            assert_eq!(pick(&master(one, other)), expected);
+            assert_eq!(pick(&master(other, one)), expected);
+        }
+    }
+
+    #[test]
+    fn variants_resolve_against_final_url() {
+        let original = "https://cbcrcott.akamaized.net/hls/live/2/master.m3u8?token=a";
+        let redirected = "https://cbcrcott-gem.akamaized.net/hls/live/2/v/master.m3u8?token=b";
+        let resolve = |url, uri: &str| {
+            let variant = Variant { uri: uri.to_owned(), bandwidth: 1 };
+            resolve_variant(url, variant).unwrap().uri
+        };
+        assert_eq!(
+            resolve(original, "720/index.m3u8"),
+            "https://cbcrcott.akamaized.net/hls/live/2/720/index.m3u8"
+        );
+        assert_eq!(
+            resolve(redirected, "720/index.m3u8"),
+            "https://cbcrcott-gem.akamaized.net/hls/live/2/v/720/index.m3u8"
+        );
+        assert_eq!(
+            resolve(redirected, "/other/720.m3u8"),
+            "https://cbcrcott-gem.akamaized.net/other/720.m3u8"
+        );
+        assert_eq!(
+            resolve(redirected, "https://elsewhere.example/720.m3u8"),
+            "https://elsewhere.example/720.m3u8"
+        );
+    }
+
+    #[test]
+    fn best_stream_resolves_against_final_url() {
+        let redirected = "https://cdn2.example/a/b/master.m3u8";
+        let best = get_best_stream(redirected, MASTER, None, None, None).unwrap();
+        assert_eq!(best.uri, "https://cdn2.example/a/b/720/index.m3u8");
+    }
+
+    #[test]
+    fn resolved_stream_json_shape() {
+        let variant =
+            Variant { uri: "https://cdn.example/720.m3u8".to_owned(), bandwidth: 3000000 };
+        let output = ResolvedStream {
+            url: &variant.uri,
+            user_agent: Some("Mozilla/5.0"),
+            referer: Some("https://www.cbc.ca/"),
+            title: "Diving",
+            show_name: None,
+            proxy: Some("socks5h://127.0.0.1:1080".to_owned()),
+            quality: None,
+            variant: Some(&variant),
+        };
+        let expected = json!({
+            "url": "https://cdn.example/720.m3u8",
+            "user_agent": "Mozilla/5.0",
+            "referer": "https://www.cbc.ca/",
+            "title": "Diving",
+            "show_name": null,
+            "proxy": "socks5h://127.0.0.1:1080",
+            "quality": null,
+            "variant": {"uri": "https://cdn.example/720.m3u8", "bandwidth": 3000000}
+        });
+        assert_eq!(serde_json::to_value(&output).unwrap(), expected);
+    }
+
+    #[test]
+    fn proxy_schemes_rewritten_only_in_the_scheme() {
+        let cases = [
+            (
+                "socks5h://socks5h.example:1080",
+                "socks5://socks5h.example:1080",
+                "socks5h://socks5h.example:1080",
+            ),
+            (
+                "socks5://socks5h.example:1080",
+                "socks5://socks5h.example:1080",
+                "socks5h://socks5h.example:1080",
+            ),
+            (
+                "socks5://user:socks5h:@host:1080",
+                "socks5://user:socks5h:@host:1080",
+                "socks5h://user:socks5h:@host:1080",
+            ),
+            (
+                "socks4://socks4.example:1080",
+                "socks4a://socks4.example:1080",
+                "socks4a://socks4.example:1080",
+            ),
+            ("SOCKS5H://Host:1080", "socks5://Host:1080", "socks5h://Host:1080"),
+            ("http://socks5h:8080", "http://socks5h:8080", "http://socks5h:8080"),
+            (
+                "socks5h.example:1080",
+                "socks5://socks5h.example:1080",
+                "socks5h://socks5h.example:1080",
+            ),
+            ("127.0.0.1:9050", "socks5://127.0.0.1:9050", "socks5h://127.0.0.1:9050"),
+        ];
+        for (spec, ureq, streamlink) in cases {
+            assert_eq!(proxy_url_ureq(spec, Dns::Remote), ureq, "{spec}");
+            assert_eq!(proxy_url_streamlink(spec, Dns::Remote), streamlink, "{spec}");
+        }
+        assert_eq!(
+            proxy_url_ureq("socks4a://socks4a.example:1", Dns::Local),
+            "socks4://socks4a.example:1"
+        );
+        assert_eq!(
+            proxy_url_streamlink("socks5h://socks5h.example:1", Dns::Local),
+            "socks5://socks5h.example:1"
+        );
+    }
+
+    #[test]
+    fn ipv6_proxy_literals() {
+        let cases = [
+            ("[2001:db8::1]:1080", "socks5://[2001:db8::1]:1080", "socks5h://[2001:db8::1]:1080"),
+            ("[::1]:9050", "socks5://[::1]:9050", "socks5h://[::1]:9050"),
+            ("socks5h://[::1]:9050", "socks5://[::1]:9050", "socks5h://[::1]:9050"),
+            (
+                "socks5://[2001:db8::1]:1080",
+                "socks5://[2001:db8::1]:1080",
+                "socks5h://[2001:db8::1]:1080",
+            ),
+            ("http://[::1]:8080", "http://[::1]:8080", "http://[::1]:8080"),
+        ];
+        for (spec, ureq, streamlink) in cases {
+            assert_eq!(proxy_url_ureq(spec, Dns::Remote), ureq, "{spec}");
+            assert_eq!(proxy_url_streamlink(spec, Dns::Remote), streamlink, "{spec}");
+        }
+    }
+
+    #[test]
+    fn ipv6_proxy_resolves_through_placeholder() {
+        use ureq::Resolver as _;
+
+        for spec in ["[::1]:9050", "socks5h://[::1]:9050"] {
+            let mut resolver = Resolver::default();
+            with_ureq_proxy(AgentBuilder::new(), spec, Dns::Remote, &mut resolver).unwrap();
+            assert_eq!(resolver.ipv6_proxy, Some(Ipv6Addr::LOCALHOST), "{spec}");
+            let addrs = resolver.resolve(&format!("{IPV6_PROXY_HOST}:9050")).unwrap();
+            assert_eq!(addrs, ["[::1]:9050".parse::<SocketAddr>().unwrap()], "{spec}");
+        }
+        let mut resolver = Resolver::default();
+        with_ureq_proxy(AgentBuilder::new(), "127.0.0.1:9050", Dns::Remote, &mut resolver).unwrap();
+        assert_eq!(resolver.ipv6_proxy, None);
+    }
+
+    #[test]
+    fn numeric_and_dotted_ids() {
+        for (input, id) in [
+            ("1.7276395", "1.7276395"),
+            ("2684119183", "2684119183"),
+            ("https://www.cbc.ca/player/play/1.7276395", "1.7276395"),
+            ("https://www.cbc.ca/player/play/2684119183", "2684119183"),
+        ] {
+            assert_eq!(probably_cbc(input).as_deref(), Ok(id), "{input}");
+        }
+        for input in [
+            "1.72763a95",
+            "26841x19183",
+            "1.2.3",
+            "12345",
+            ".7276395",
+            "https://www.cbc.ca/player/play/1.7276395x",
+            "https://www.cbc.ca/player/play/2684119183.",
+        ] {
+            let error = probably_cbc(input).unwrap_err();
+            assert!(error.starts_with("invalid url"), "{input}: {error}");
+        }
+    }
+
+    #[test]
+    fn ids_from_real_world_urls() {
+        let cases = [
+            "https://www.cbc.ca/player/play/video/1.7276395",
+            "https://www.cbc.ca/player/play/video/1.7276395?cmp=share&fbclid=IwAR0abc",
+            "https://www.cbc.ca/player/play/video/1.7276395#comments",
+            "https://www.cbc.ca/player/play/video/1.7276395/",
+            "http://www.cbc.ca/player/play/video/1.7276395",
+            "www.cbc.ca/player/play/video/1.7276395",
+            "cbc.ca/player/play/video/1.7276395",
+            "https://m.cbc.ca/player/play/video/1.7276395",
+            "https://www.cbc.ca/player/play/1.7276395",
+            "https://www.cbc.ca/player/play/1.7276395?autoplay=true",
+            "https://www.cbc.ca/player/play/video/1.7276395?cmp=rss#t=10",
+            "  https://www.cbc.ca/player/play/video/1.7276395  ",
+        ];
+        for input in cases {
+            assert_eq!(parse_cbc_id(input).unwrap(), "1.7276395", "{input}");
+        }
+        let rejected = [
+            "https://www.cbc.ca/player/play/video/?id=1.7276395",
+            "https://www.cbc.ca/player/play/video/1.7276395/extra",
+            "https://www.cbc.ca/news/1.7276395",
+            "https://www.example.com/player/play/video/1.7276395",
+            "ftp://www.cbc.ca/player/play/video/1.7276395",
+            "https://www.cbc.ca/player/play/video/",
+        ];
+        for input in rejected {
+            assert!(parse_cbc_id(input).is_err(), "{input}");
+        }
+    }
+
+    #[test]
+    fn short_share_links() {
+        for input in [
+            "https://www.cbc.ca/player/1.7276395",
+            "cbc.ca/player/1.7276395?cmp=share",
+            "https://m.cbc.ca/player/1.7276395/",
+        ] {
+            assert_eq!(parse_cbc_id(input).unwrap(), "1.7276395", "{input}");
+        }
+        assert!(parse_cbc_id("https://www.cbc.ca/player/sports").is_err());
+    }
+
+    #[test]
+    fn app_deep_links() {
+        for input in [
+            "cbcnews://video/1.7276395",
+            "cbcgem://player/play/video/1.7276395",
+            "cbcsports://1.7276395",
+            "cbcnews://video/1.7276395/",
+        ] {
+            assert_eq!(parse_cbc_id(input).unwrap(), "1.7276395", "{input}");
+        }
+        // only the modern dotted form is used in app links
+        assert!(parse_cbc_id("cbcnews://video/2684119183").is_err());
+        assert!(parse_cbc_id("cbcnews://video/").is_err());
+    }
+
+    #[test]
+    fn csv_fields_quoted_per_rfc_4180() {
+        assert_eq!(csv_field("Diving"), "Diving");
+        assert_eq!(csv_field(""), "");
+        assert_eq!(
+            csv_field("Diving, women's \"10 m\"\nfinal"),
+            "\"Diving, women's \"\"10 m\"\"\nfinal\""
+        );
+        assert_eq!(csv_field("a\r\nb"), "\"a\r\nb\"");
+        assert_eq!(csv_field("\""), "\"\"\"\"");
+    }
 }
@@ -0,0 +1,52 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use once_cell::sync::OnceCell;
+use ureq::{Middleware, MiddlewareNext, Request, Response};
+
+use crate::debug::debug;
+
+static FIXTURES: OnceCell<Fixtures> = OnceCell::new();
+
+/// The saved responses `--offline` uses in place of fetching: the player page, the medianet
+/// JSON and the master playlist.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Fixtures {
+    pub(crate) page: String,
+    pub(crate) stream: Option<String>,
+    pub(crate) playlist: Option<String>,
+}
+
+/// Read the `--offline` files, all up front, so a bad path fails before anything else happens.
+pub(crate) fn init(page: &Path, stream: Option<&Path>, playlist: Option<&Path>) -> Result<()> {
+    let read = |path: &Path| {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("reading --offline file {}", path.display()))?;
+        debug!("offline file={} bytes={}", path.display(), contents.len());
+        anyhow::Ok(contents)
+    };
+    let fixtures = Fixtures {
+        page: read(page)?,
+        stream: stream.map(read).transpose()?,
+        playlist: playlist.map(read).transpose()?,
+    };
+    let _ = FIXTURES.set(fixtures);
+    Ok(())
+}
+
+/// The `--offline` files, or `None` when online.
+pub(crate) fn fixtures() -> Option<&'static Fixtures> {
+    FIXTURES.get()
+}
+
+/// Fails every request, so nothing that hasn't been given a file can reach the network.
+pub(crate) struct NoNetwork;
+
+impl Middleware for NoNetwork {
+    fn handle(&self, request: Request, _next: MiddlewareNext) -> Result<Response, ureq::Error> {
+        let message = format!("--offline, so not fetching {}", request.url());
+        Err(io::Error::other(message).into())
+    }
+}
@@ -0,0 +1,46 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+
+use crate::exit::{Code, Failure};
+
+/// A named set of listing filters from the presets file, for `--preset`.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Preset {
+    /// Sports to show, as with `--sport`
+    #[serde(default)]
+    pub(crate) sport: Vec<String>,
+}
+
+fn path() -> Result<PathBuf> {
+    let dir = dirs::config_dir().ok_or_else(|| anyhow!("couldn't find config directory"))?;
+    Ok(dir.join("cbc-sl").join("presets.json"))
+}
+
+/// Look up the preset called `name`. The presets file is a JSON object of them by name, e.g.
+/// `{"swim": {"sport": ["swimming", "diving"]}}`.
+pub(crate) fn get(name: &str) -> Result<Preset> {
+    let path = path()?;
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let message =
+                format!("there's no presets file; create {} to use --preset", path.display());
+            return Err(Failure::new(Code::InvalidInput, message).into());
+        }
+        Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+    };
+    let mut presets: BTreeMap<String, Preset> =
+        serde_json::from_str(&contents).with_context(|| format!("reading {}", path.display()))?;
+    presets.remove(name).ok_or_else(|| {
+        let names: Vec<_> = presets.keys().map(String::as_str).collect();
+        let names = if names.is_empty() { "none".to_owned() } else { names.join(", ") };
+        let message = format!("no preset called '{name}'; {} has: {names}", path.display());
+        Failure::new(Code::InvalidInput, message).into()
+    })
+}
@@ -0,0 +1,171 @@
+use hls_m3u8::tags::VariantStream;
+
+use crate::VariantStreamExt;
+
+/// A `--quality` to pick ourselves with `--distrust-streamlink`: streamlink-style names tried in
+/// order, e.g. `720p,best`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Quality(Vec<Token>);
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Best,
+    Worst,
+    /// `720p` is any variant 720 pixels high, whatever its width; `720p60` also needs the frame
+    /// rate to match
+    Height {
+        height: u32,
+        fps: Option<u32>,
+    },
+    /// Something streamlink might understand (`audio`, `720p_alt`) that we can't pick by
+    Other(String),
+}
+
+impl Quality {
+    /// Parse a comma-separated list of qualities. Anything unrecognized is kept, and only
+    /// reported by [Quality::pick] if it's reached, so the error can say what's there instead.
+    pub(crate) fn parse(input: &str) -> Self {
+        let height = lazy_regex::regex!(r#"^([[:digit:]]+)p([[:digit:]]+)?$"#);
+        let tokens = input.split(',').map(str::trim).filter(|q| !q.is_empty()).map(|q| match q {
+            "best" | "best-unfiltered" => Token::Best,
+            "worst" | "worst-unfiltered" => Token::Worst,
+            _ => match height.captures(q) {
+                Some(caps) => match caps[1].parse() {
+                    Ok(height) => Token::Height {
+                        height,
+                        fps: caps.get(2).and_then(|fps| fps.as_str().parse().ok()),
+                    },
+                    Err(_) => Token::Other(q.to_owned()),
+                },
+                None => Token::Other(q.to_owned()),
+            },
+        });
+        Self(tokens.collect())
+    }
+
+    /// The first variant matching a quality, trying each in order; among several matches, the
+    /// highest bandwidth wins. `variants` must be sorted by bandwidth, lowest first. Fails with a
+    /// message listing the qualities there are if nothing matches.
+    pub(crate) fn pick<'a, 'b>(
+        &self,
+        variants: &'a [VariantStream<'b>],
+    ) -> Result<&'a VariantStream<'b>, String> {
+        for token in &self.0 {
+            let found = match token {
+                Token::Best => variants.last(),
+                Token::Worst => variants.first(),
+                Token::Height { height, fps } => variants.iter().rev().find(|v| {
+                    let matches_height = v.resolution().is_some_and(|(_, h)| h == *height);
+                    let matches_fps = fps.is_none_or(|fps| {
+                        v.frame_rate().is_some_and(|rate| rate.round() as u32 == fps)
+                    });
+                    matches_height && matches_fps
+                }),
+                Token::Other(other) => {
+                    return Err(format!(
+                        "'{other}' isn't a quality cbc-sl can pick; this stream has {}",
+                        available(variants)
+                    ));
+                }
+            };
+            if let Some(found) = found {
+                return Ok(found);
+            }
+        }
+        Err(format!(
+            "none of those qualities are available; this stream has {}",
+            available(variants)
+        ))
+    }
+}
+
+/// The variants' names, highest bandwidth first, for error messages.
+fn available(variants: &[VariantStream<'_>]) -> String {
+    let mut names: Vec<String> = variants.iter().rev().filter_map(|v| v.quality_name()).collect();
+    names.dedup();
+    if names.is_empty() {
+        "no named qualities".to_owned()
+    } else {
+        names.join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hls_m3u8::MasterPlaylist;
+
+    use super::*;
+
+    const LADDER: &str = "#EXTM3U
+#EXT-X-STREAM-INF:BANDWIDTH=5000000,RESOLUTION=1920x1080,FRAME-RATE=30.000
+1080.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=4500000,RESOLUTION=1280x720,FRAME-RATE=59.940
+720p60.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=3000000,RESOLUTION=1280x720,FRAME-RATE=29.970
+720p30.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=1500000,RESOLUTION=960x540,FRAME-RATE=29.970
+540.m3u8
+";
+
+    /// The URI `quality` picks from `playlist`, or the error.
+    fn pick(quality: &str, playlist: &str) -> Result<String, String> {
+        let mut variants = MasterPlaylist::try_from(playlist).unwrap().variant_streams;
+        variants.sort_by_key(|v| v.bandwidth());
+        Quality::parse(quality).pick(&variants).map(|v| v.uri())
+    }
+
+    #[test]
+    fn parsed() {
+        let quality = Quality::parse("720p60, worst-unfiltered,,best,audio");
+        let expected = Quality(vec![
+            Token::Height { height: 720, fps: Some(60) },
+            Token::Worst,
+            Token::Best,
+            Token::Other("audio".to_owned()),
+        ]);
+        assert_eq!(quality, expected);
+    }
+
+    #[test]
+    fn frame_rates_matched() {
+        assert_eq!(pick("720p60", LADDER).unwrap(), "720p60.m3u8");
+        assert_eq!(pick("720p30", LADDER).unwrap(), "720p30.m3u8");
+        // without a frame rate, the best 720p there is
+        assert_eq!(pick("720p", LADDER).unwrap(), "720p60.m3u8");
+        assert_eq!(pick("best", LADDER).unwrap(), "1080.m3u8");
+        assert_eq!(pick("worst", LADDER).unwrap(), "540.m3u8");
+    }
+
+    #[test]
+    fn height_matched_whatever_the_width() {
+        let playlist = "#EXTM3U
+#EXT-X-STREAM-INF:BANDWIDTH=3000000,RESOLUTION=960x720
+4x3.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=5000000,RESOLUTION=1920x1080
+1080.m3u8
+";
+        assert_eq!(pick("720p", playlist).unwrap(), "4x3.m3u8");
+    }
+
+    #[test]
+    fn falls_back_in_order() {
+        assert_eq!(pick("720p,best", LADDER).unwrap(), "720p60.m3u8");
+        assert_eq!(pick("480p,720p,best", LADDER).unwrap(), "720p60.m3u8");
+        let no_720 = LADDER.replace("RESOLUTION=1280x720", "RESOLUTION=1024x576");
+        assert_eq!(pick("720p,best", &no_720).unwrap(), "1080.m3u8");
+        assert_eq!(
+            pick("480p,360p", LADDER).unwrap_err(),
+            "none of those qualities are available; this stream has 1080p, 720p60, 720p, 540p"
+        );
+    }
+
+    #[test]
+    fn unpickable_quality_rejected() {
+        assert_eq!(
+            pick("720p_alt,best", LADDER).unwrap_err(),
+            "'720p_alt' isn't a quality cbc-sl can pick; this stream has 1080p, 720p60, 720p, 540p"
+        );
+        // only once it's reached
+        assert_eq!(pick("1080p,720p_alt", LADDER).unwrap(), "1080.m3u8");
+    }
+}
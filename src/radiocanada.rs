@@ -0,0 +1,75 @@
+use url::Url;
+
+/// Radio-Canada's player pages are served from here, and its streams expect it as the Referer.
+pub(crate) const REFERER: &str = "https://ici.radio-canada.ca/";
+
+/// The media validation endpoint Radio-Canada's players get streams from. CBC's medianet assets
+/// point at the same service, so the response is the same shape.
+const VALIDATION_URL: &str = "https://services.radio-canada.ca/media/validation/v2/";
+
+/// A Radio-Canada video, identified the way its media console is: an app code (nearly always
+/// `medianet`) and a numeric media ID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Media {
+    app_code: String,
+    id: String,
+}
+
+impl Media {
+    /// Recognize a Radio-Canada link: a media console widget
+    /// (`ici.radio-canada.ca/widgets/mediaconsole/medianet/7184272`), a page with a
+    /// `media-7184272` path segment, or the `radiocanada:medianet:7184272` form [Media::to_id]
+    /// gives.
+    pub(crate) fn parse(input: &str) -> Option<Self> {
+        if let Some(rest) = input.strip_prefix("radiocanada:") {
+            let (app_code, id) = rest.split_once(':')?;
+            return Self::new(app_code, id);
+        }
+        let url = if input.contains("://") {
+            Url::parse(input)
+        } else {
+            Url::parse(&format!("https://{input}"))
+        }
+        .ok()?;
+        if !matches!(url.host_str()?, "ici.radio-canada.ca" | "radio-canada.ca") {
+            return None;
+        }
+        let segments: Vec<&str> = url.path_segments()?.filter(|s| !s.is_empty()).collect();
+        match segments.as_slice() {
+            ["widgets", "mediaconsole", app_code, id, ..] => Self::new(app_code, id),
+            segments => segments
+                .iter()
+                .find_map(|segment| segment.strip_prefix("media-"))
+                .and_then(|id| Self::new("medianet", id)),
+        }
+    }
+
+    fn new(app_code: &str, id: &str) -> Option<Self> {
+        let valid = !app_code.is_empty()
+            && app_code.chars().all(|c| c.is_ascii_alphanumeric())
+            && !id.is_empty()
+            && id.chars().all(|c| c.is_ascii_digit());
+        valid.then(|| Self { app_code: app_code.to_owned(), id: id.to_owned() })
+    }
+
+    /// A form that stands in for a CBC video ID, so a Radio-Canada link can go wherever one
+    /// does, and that [Media::parse] reads back.
+    pub(crate) fn to_id(&self) -> String {
+        format!("radiocanada:{}:{}", self.app_code, self.id)
+    }
+
+    /// Where to get the stream's master playlist URL from.
+    pub(crate) fn validation_url(&self) -> String {
+        let mut url = Url::parse(VALIDATION_URL).unwrap();
+        url.query_pairs_mut()
+            .append_pair("appCode", &self.app_code)
+            .append_pair("connectionType", "hd")
+            .append_pair("deviceType", "ipad")
+            .append_pair("idMedia", &self.id)
+            .append_pair("multibitrate", "true")
+            .append_pair("output", "json")
+            .append_pair("tech", "hls")
+            .append_pair("manifestVersion", "2");
+        url.into()
+    }
+}
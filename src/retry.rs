@@ -0,0 +1,61 @@
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use jiff::Timestamp;
+use owo_colors::{OwoColorize, Stream::Stderr};
+use ureq::{Request, Response};
+
+use crate::debug::debug;
+
+/// How long to wait when CBC doesn't say, in seconds. Doubled for each retry.
+const DEFAULT_WAIT: u64 = 5;
+
+static MAX_WAIT: AtomicU64 = AtomicU64::new(30);
+static RETRIES: AtomicU32 = AtomicU32::new(2);
+
+/// Set the longest we'll wait before retrying a rate-limited request and how many times to try
+/// again, from `--max-retry-wait` and `--retries`.
+pub(crate) fn init(max_wait_secs: u64, retries: u32) {
+    MAX_WAIT.store(max_wait_secs, Ordering::Relaxed);
+    RETRIES.store(retries, Ordering::Relaxed);
+}
+
+/// Send `request`, with `json` as the body if given. If CBC rate-limits it (HTTP 429), wait as
+/// long as its `Retry-After` asks (up to `--max-retry-wait`) and try again, a couple of times
+/// at most (`--retries`).
+pub(crate) fn call(
+    request: Request,
+    json: Option<&serde_json::Value>,
+) -> Result<Response, Box<ureq::Error>> {
+    let mut attempt = 0;
+    loop {
+        let result = match json {
+            Some(json) => request.clone().send_json(json),
+            None => request.clone().call(),
+        };
+        match result {
+            Err(ureq::Error::Status(429, resp)) if attempt < RETRIES.load(Ordering::Relaxed) => {
+                let max = MAX_WAIT.load(Ordering::Relaxed);
+                let backoff = DEFAULT_WAIT << attempt.min(6);
+                let wait = retry_after(&resp).unwrap_or(backoff).min(max);
+                attempt += 1;
+                debug!("rate_limited url={} wait={wait} attempt={attempt}", resp.get_url());
+                let note = format!("rate limited, waiting {wait}s");
+                eprintln!("{}", note.if_supports_color(Stderr, |text| text.dimmed()));
+                thread::sleep(Duration::from_secs(wait));
+            }
+            result => return result.map_err(Box::new),
+        }
+    }
+}
+
+/// Seconds to wait according to `Retry-After`, which is either a number of seconds or a date.
+fn retry_after(resp: &Response) -> Option<u64> {
+    let value = resp.header("Retry-After")?.trim();
+    if let Ok(seconds) = value.parse() {
+        return Some(seconds);
+    }
+    let at = jiff::fmt::rfc2822::parse(value).ok()?.timestamp();
+    Some((at - Timestamp::now()).get_seconds().max(0) as u64)
+}
@@ -0,0 +1,96 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+use crate::debug::debug;
+use crate::exit::{Code, Failure};
+
+/// The kinds of internet shortcut we can write, picked by the file's extension.
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+enum Kind {
+    /// Windows `.url`
+    Url,
+    /// freedesktop.org `.desktop`, for Linux desktops
+    Desktop,
+    /// macOS `.webloc`
+    Webloc,
+}
+
+/// Write a shortcut to `url` at `path`, in the format its extension calls for.
+///
+/// `command` is the program and arguments that would play `url` with the right headers. Only a
+/// `.desktop` file can run a command, so the others just point at `url` and anything opening
+/// them won't send CBC's headers.
+pub(crate) fn write(path: &Path, title: &str, url: &str, command: Option<&[String]>) -> Result<()> {
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or_default();
+    let kind = match ext.to_ascii_lowercase().as_str() {
+        "url" => Kind::Url,
+        "desktop" => Kind::Desktop,
+        "webloc" => Kind::Webloc,
+        _ => bail!(Failure::new(
+            Code::InvalidInput,
+            format!(
+                "can't tell what kind of shortcut {} should be; end it in .url (Windows), \
+                .desktop (Linux) or .webloc (macOS)",
+                path.display()
+            )
+        )),
+    };
+    let contents = match (kind, command) {
+        (Kind::Url, _) => format!("[InternetShortcut]\r\nURL={url}\r\n"),
+        (Kind::Desktop, None) => desktop_entry(title, "Link", &format!("URL={}", escape(url))),
+        (Kind::Desktop, Some(command)) => {
+            let exec: Vec<_> = command.iter().map(|arg| quote_exec_arg(arg)).collect();
+            desktop_entry(title, "Application", &format!("Exec={}", escape(&exec.join(" "))))
+        }
+        (Kind::Webloc, _) => webloc(url),
+    };
+    if command.is_some() && kind != Kind::Desktop {
+        eprintln!(
+            "note: .{ext} shortcuts can't carry HTTP headers; whatever opens it must send \
+            CBC's User-Agent"
+        );
+    }
+    debug!("shortcut kind={kind:?} path={}", path.display());
+    fs::write(path, contents).with_context(|| format!("writing {}", path.display()))
+}
+
+fn desktop_entry(title: &str, kind: &str, target: &str) -> String {
+    format!("[Desktop Entry]\nVersion=1.0\nType={kind}\nName={}\n{target}\n", escape(title))
+}
+
+fn webloc(url: &str) -> String {
+    let url = url.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+        <!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \
+        \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+        <plist version=\"1.0\">\n<dict>\n\t<key>URL</key>\n\t<string>{url}</string>\n\
+        </dict>\n</plist>\n"
+    )
+}
+
+/// Escape a `.desktop` string value.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\n', "\\n").replace('\t', "\\t").replace('\r', "\\r")
+}
+
+/// Quote an argument for a `.desktop` `Exec` key. `%` starts a field code, so it's doubled.
+fn quote_exec_arg(arg: &str) -> String {
+    let arg = arg.replace('%', "%%");
+    if !arg.is_empty()
+        && !arg.contains(|c: char| c.is_whitespace() || "\"'\\><~|&;$*?#()`".contains(c))
+    {
+        return arg;
+    }
+    let mut quoted = String::from('"');
+    for c in arg.chars() {
+        if matches!(c, '"' | '`' | '$' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
@@ -0,0 +1,86 @@
+use std::fs;
+use std::path::PathBuf;
+
+use jiff::Timestamp;
+use serde::{Deserialize, Serialize};
+
+use crate::debug::debug;
+
+/// How long a cached stream is used for. Master playlist URLs carry tokens that expire, so this
+/// only covers running cbc-sl again straight away, e.g. `url` and then `play`.
+const TTL_SECS: i64 = 120;
+
+/// A resolved video: its player state and master playlist URL, saved so running cbc-sl again for
+/// the same ID can skip fetching the player page and medianet.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Entry {
+    /// Seconds since the Unix epoch
+    timestamp: i64,
+    /// The player state JSON, as found on the page
+    pub(crate) initial_state: String,
+    /// The asset the URL came from, since `--asset` and `--prefer-dai` can change it
+    pub(crate) asset_type: String,
+    pub(crate) master_url: String,
+}
+
+impl Entry {
+    pub(crate) fn new(initial_state: &str, asset_type: &str, master_url: &str) -> Self {
+        Self {
+            timestamp: Timestamp::now().as_second(),
+            initial_state: initial_state.to_owned(),
+            asset_type: asset_type.to_owned(),
+            master_url: master_url.to_owned(),
+        }
+    }
+}
+
+/// Where the entry for `id` is kept, or `None` if `id` isn't safe to use as a file name.
+fn path(id: &str) -> Option<PathBuf> {
+    let safe = |c: char| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_');
+    if !id.starts_with(|c: char| c.is_ascii_alphanumeric()) || !id.chars().all(safe) {
+        return None;
+    }
+    Some(dirs::cache_dir()?.join("cbc-sl").join("streams").join(format!("{id}.json")))
+}
+
+/// The entry for `id`, if one was saved less than [TTL_SECS] ago. The cache is only a
+/// shortcut, so anything wrong with it just means fetching as usual.
+pub(crate) fn get(id: &str) -> Option<Entry> {
+    let path = path(id)?;
+    let contents = fs::read_to_string(&path).ok()?;
+    let entry: Entry = match serde_json::from_str(&contents) {
+        Ok(entry) => entry,
+        Err(e) => {
+            debug!("stream_cache unreadable file={} error={e}", path.display());
+            return None;
+        }
+    };
+    let age = Timestamp::now().as_second() - entry.timestamp;
+    if !(0..TTL_SECS).contains(&age) {
+        debug!("stream_cache expired id={id} age={age}");
+        return None;
+    }
+    debug!("stream_cache hit id={id} age={age}");
+    Some(entry)
+}
+
+/// Save the entry for `id`. Failing to is only logged.
+pub(crate) fn save(id: &str, entry: &Entry) {
+    let Some(path) = path(id) else { return };
+    let result = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|()| fs::write(&path, serde_json::to_string(entry).unwrap_or_default()));
+    match result {
+        Ok(()) => debug!("stream_cache saved id={id}"),
+        Err(e) => debug!("stream_cache save_failed file={} error={e}", path.display()),
+    }
+}
+
+/// Remove the entry for `id`, if there is one.
+pub(crate) fn forget(id: &str) {
+    if let Some(path) = path(id).filter(|path| path.exists()) {
+        debug!("stream_cache forget id={id}");
+        let _ = fs::remove_file(path);
+    }
+}
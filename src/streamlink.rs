@@ -0,0 +1,374 @@
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::debug::debug;
+use crate::error::CbcError;
+use crate::exit::{Code, Failure};
+
+/// How many lines of streamlink's output to keep for error messages.
+const TAIL_LINES: usize = 5;
+
+/// What streamlink logs once the stream's data is flowing to the player or file. Older versions
+/// say "Writing stream to output".
+const STARTED_MARKERS: &[&str] =
+    &["Starting player", "Writing output to", "Writing stream to output"];
+
+/// The most streamlinks [run_all] will run at once.
+pub(crate) const MAX_RUNNING: usize = 8;
+
+/// How often to check on streamlink while waiting for it to start.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Run streamlink, passing its output through to the terminal as it arrives. If it fails, the
+/// last few lines it printed are included in the error, since the exit code alone says nothing
+/// about *why* (geo-blocking, no playable streams, etc.)
+///
+/// With a `launch_timeout`, streamlink is killed if it hasn't started playing by then, since it
+/// can otherwise wait forever on a dead stream.
+pub(crate) fn run(mut cmd: Command, launch_timeout: Option<Duration>) -> Result<()> {
+    let mut child = spawn(cmd.stdout(Stdio::piped()).stderr(Stdio::piped()))?;
+    #[cfg(windows)]
+    let _job = crate::winjob::contain(&[&child])?;
+    #[cfg(unix)]
+    let signals = crate::unixsignals::forward_to(&[&child])?;
+    let stdout = child.stdout.take().unwrap();
+    let stderr = child.stderr.take().unwrap();
+    let tail = Mutex::new(VecDeque::with_capacity(TAIL_LINES));
+    let started = AtomicBool::new(false);
+    let status = thread::scope(|s| {
+        s.spawn(|| tee(stdout, io::stdout(), None, &tail, &started));
+        s.spawn(|| tee(stderr, io::stderr(), None, &tail, &started));
+        await_start(&mut child, launch_timeout, &started)?;
+        Ok::<_, anyhow::Error>(child.wait()?)
+    })?;
+    #[cfg(unix)]
+    drop(signals);
+    check_interrupted()?;
+    check(status, tail.into_inner().unwrap())
+}
+
+/// Run streamlink with `-O`, feeding the stream into `consumer`, a shell command line. If the
+/// consumer exits first, streamlink is killed rather than left downloading into a closed pipe.
+/// `launch_timeout` works as with [run], counting from when the stream's first data arrives.
+pub(crate) fn run_piped(
+    mut cmd: Command,
+    consumer: &str,
+    launch_timeout: Option<Duration>,
+) -> Result<()> {
+    let mut child = spawn(cmd.arg("-O").stdout(Stdio::piped()).stderr(Stdio::piped()))?;
+    let stream = child.stdout.take().unwrap();
+    let stderr = child.stderr.take().unwrap();
+    // To see when data starts flowing, the stream has to pass through us; otherwise the consumer
+    // reads it straight from streamlink
+    let (stdin, pumped) = match launch_timeout {
+        Some(_) => (Stdio::piped(), Some(stream)),
+        None => (Stdio::from(stream), None),
+    };
+    let mut consumer_child = match shell(consumer).stdin(stdin).spawn() {
+        Ok(consumer_child) => consumer_child,
+        Err(e) => {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(e).with_context(|| format!("running {consumer}"));
+        }
+    };
+    #[cfg(windows)]
+    let _job = crate::winjob::contain(&[&child, &consumer_child])?;
+    #[cfg(unix)]
+    let signals = crate::unixsignals::forward_to(&[&child, &consumer_child])?;
+    let consumer_stdin = consumer_child.stdin.take();
+    let tail = Mutex::new(VecDeque::with_capacity(TAIL_LINES));
+    let started = AtomicBool::new(false);
+    let (status, consumer_status) = thread::scope(|s| {
+        s.spawn(|| tee(stderr, io::stderr(), None, &tail, &started));
+        if let (Some(from), Some(to)) = (pumped, consumer_stdin) {
+            let started = &started;
+            s.spawn(move || pump(from, to, started));
+        }
+        let stalled = await_start(&mut child, launch_timeout, &started);
+        let consumer_status = consumer_child.wait();
+        if let Ok(None) = child.try_wait() {
+            let _ = child.kill();
+        }
+        let status = child.wait();
+        stalled.map(|()| (status, consumer_status))
+    })?;
+    #[cfg(unix)]
+    drop(signals);
+    check_interrupted()?;
+    let consumer_status = consumer_status?;
+    if !consumer_status.success() {
+        bail!("{consumer} exited with {consumer_status}");
+    }
+    check(status?, tail.into_inner().unwrap())
+}
+
+/// Run several streamlinks at once, each labelled with a short name that its output lines are
+/// tagged with, and wait for all of them. A failure is reported as it happens and the rest carry
+/// on, unless `fail_fast` is set, in which case the others are stopped too. Ctrl-C stops them
+/// all. `launch_timeout` applies to each separately.
+pub(crate) fn run_all(
+    cmds: Vec<(String, Command)>,
+    launch_timeout: Option<Duration>,
+    fail_fast: bool,
+) -> Result<()> {
+    let mut children = Vec::with_capacity(cmds.len());
+    let mut labels = Vec::with_capacity(cmds.len());
+    for (label, mut cmd) in cmds {
+        match spawn(cmd.stdout(Stdio::piped()).stderr(Stdio::piped())) {
+            Ok(child) => children.push(child),
+            Err(e) => {
+                for child in &mut children {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                }
+                return Err(e);
+            }
+        }
+        labels.push(label);
+    }
+    let contained: Vec<_> = children.iter().collect();
+    #[cfg(windows)]
+    let _job = crate::winjob::contain(&contained)?;
+    #[cfg(unix)]
+    let signals = crate::unixsignals::forward_to(&contained)?;
+    let outputs: Vec<_> = children
+        .iter_mut()
+        .map(|child| (child.stdout.take().unwrap(), child.stderr.take().unwrap()))
+        .collect();
+    let tails: Vec<_> =
+        labels.iter().map(|_| Mutex::new(VecDeque::with_capacity(TAIL_LINES))).collect();
+    let started: Vec<_> = labels.iter().map(|_| AtomicBool::new(false)).collect();
+    let failures = thread::scope(|s| {
+        for (i, (stdout, stderr)) in outputs.into_iter().enumerate() {
+            let (label, tail, started) = (labels[i].as_str(), &tails[i], &started[i]);
+            s.spawn(move || tee(stdout, io::stdout(), Some(label), tail, started));
+            s.spawn(move || tee(stderr, io::stderr(), Some(label), tail, started));
+        }
+        supervise(&mut children, &labels, &tails, &started, launch_timeout, fail_fast)
+    })?;
+    #[cfg(unix)]
+    drop(signals);
+    check_interrupted()?;
+    if !failures.is_empty() {
+        bail!("{} of {} streams failed", failures.len(), labels.len());
+    }
+    Ok(())
+}
+
+/// Wait for every child of [run_all] to exit, killing any that stall and, with `fail_fast`,
+/// the rest once one fails. Returns a description of each failure, which is also printed as it
+/// happens.
+fn supervise(
+    children: &mut [Child],
+    labels: &[String],
+    tails: &[Mutex<VecDeque<String>>],
+    started: &[AtomicBool],
+    launch_timeout: Option<Duration>,
+    fail_fast: bool,
+) -> Result<Vec<String>> {
+    let deadline = launch_timeout.map(|timeout| Instant::now() + timeout);
+    let mut done = vec![false; children.len()];
+    let mut failures = Vec::new();
+    let mut stopping = false;
+    while done.iter().any(|done| !done) {
+        for i in 0..children.len() {
+            if done[i] {
+                continue;
+            }
+            let child = &mut children[i];
+            let failure = match child.try_wait()? {
+                None if deadline.is_some_and(|deadline| Instant::now() >= deadline)
+                    && !started[i].load(Ordering::Relaxed) =>
+                {
+                    let seconds = launch_timeout.unwrap_or_default().as_secs();
+                    debug!("streamlink_stalled label={} secs={seconds}", labels[i]);
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    Some(anyhow::Error::from(CbcError::StreamlinkStalled { seconds }))
+                }
+                None => continue,
+                // these were stopped on purpose, so their exit status says nothing
+                Some(_) if stopping || interrupted() => None,
+                Some(status) => {
+                    let tail = std::mem::take(&mut *tails[i].lock().unwrap());
+                    check(status, tail).err()
+                }
+            };
+            done[i] = true;
+            let Some(failure) = failure else { continue };
+            let failure = format!("[{}] {failure:#}", labels[i]);
+            eprintln!("{failure}");
+            failures.push(failure);
+            if fail_fast && !stopping {
+                stopping = true;
+                for j in (0..children.len()).filter(|&j| !done[j]) {
+                    debug!("fail_fast stopping={}", labels[j]);
+                    let _ = children[j].kill();
+                }
+            }
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+    Ok(failures)
+}
+
+/// With a `launch_timeout`, wait until streamlink has started playing or has exited, killing it
+/// if neither has happened in time.
+fn await_start(
+    child: &mut Child,
+    launch_timeout: Option<Duration>,
+    started: &AtomicBool,
+) -> Result<()> {
+    let Some(timeout) = launch_timeout else { return Ok(()) };
+    let deadline = Instant::now() + timeout;
+    while !started.load(Ordering::Relaxed) && child.try_wait()?.is_none() {
+        if Instant::now() >= deadline {
+            debug!("streamlink_stalled secs={}", timeout.as_secs());
+            let _ = child.kill();
+            let _ = child.wait();
+            bail!(CbcError::StreamlinkStalled { seconds: timeout.as_secs() });
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+    Ok(())
+}
+
+fn spawn(cmd: &mut Command) -> Result<Child> {
+    let program = cmd.get_program().to_string_lossy().into_owned();
+    // its own process group, so signals can be passed on to the player it starts as well
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(cmd, 0);
+    match cmd.spawn() {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Err(e).context(Failure::new(
+            Code::StreamlinkMissing,
+            format!("couldn't find {program}; is streamlink installed and on your PATH?"),
+        )),
+        result => result.with_context(|| format!("running {program}")),
+    }
+}
+
+/// A command line run through the platform's shell, so users can pass pipelines and arguments
+/// the same way they would type them.
+fn shell(command: &str) -> Command {
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        let mut cmd = Command::new("cmd");
+        // cmd.exe does its own unquoting, so the command line must be passed through untouched
+        cmd.arg("/C").raw_arg(command);
+        cmd
+    }
+    #[cfg(not(windows))]
+    {
+        use std::os::unix::process::CommandExt;
+        let mut cmd = Command::new("sh");
+        // like streamlink's, so signals passed on reach the whole pipeline
+        cmd.arg("-c").arg(command).process_group(0);
+        cmd
+    }
+}
+
+/// On Windows, Ctrl-C kills streamlink and its player (see [crate::winjob]). Say so, rather than
+/// reporting whatever exit code that left behind. On Unix, a signal passed on to streamlink
+/// (see [crate::unixsignals]) now ends cbc-sl too, with the matching exit code.
+fn check_interrupted() -> Result<()> {
+    #[cfg(windows)]
+    if crate::winjob::interrupted() {
+        bail!(Failure::new(Code::Interrupted, "interrupted"));
+    }
+    #[cfg(unix)]
+    if let Some(failure) = crate::unixsignals::take_failure() {
+        bail!(failure);
+    }
+    Ok(())
+}
+
+/// Whether the user interrupted streamlink (see [check_interrupted]), without acting on it yet.
+fn interrupted() -> bool {
+    #[cfg(windows)]
+    return crate::winjob::interrupted();
+    #[cfg(unix)]
+    return crate::unixsignals::received();
+    #[allow(unreachable_code)]
+    false
+}
+
+/// Turn streamlink's exit status into an error with its last lines of output, if it failed.
+fn check(status: ExitStatus, tail: VecDeque<String>) -> Result<()> {
+    if status.success() {
+        return Ok(());
+    }
+    #[cfg(unix)]
+    let signal = std::os::unix::process::ExitStatusExt::signal(&status);
+    #[cfg(not(unix))]
+    let signal = None;
+    let reason = last_error(&tail);
+    let failed = CbcError::StreamlinkFailed { code: status.code(), signal, reason };
+    if tail.is_empty() {
+        return Err(failed.into());
+    }
+    Err(anyhow!("{}", Vec::from(tail).join("\n")).context(failed))
+}
+
+/// Copy lines from `from` to `to`, prefixed with `[label]` if given, remembering the most recent
+/// ones in `tail` and setting `started` once streamlink says the stream is playing.
+fn tee(
+    from: impl Read,
+    mut to: impl Write,
+    label: Option<&str>,
+    tail: &Mutex<VecDeque<String>>,
+    started: &AtomicBool,
+) {
+    for line in BufReader::new(from).lines() {
+        let Ok(line) = line else { break };
+        let _ = match label {
+            Some(label) => writeln!(to, "[{label}] {line}"),
+            None => writeln!(to, "{line}"),
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        if STARTED_MARKERS.iter().any(|marker| line.contains(marker)) {
+            started.store(true, Ordering::Relaxed);
+        }
+        let mut tail = tail.lock().unwrap();
+        if tail.len() == TAIL_LINES {
+            tail.pop_front();
+        }
+        tail.push_back(line);
+    }
+}
+
+/// Copy the stream from streamlink to the consumer, setting `started` when the first data
+/// arrives. Stops when either end closes.
+fn pump(mut from: impl Read, mut to: impl Write, started: &AtomicBool) {
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        match from.read(&mut buf) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => {
+                started.store(true, Ordering::Relaxed);
+                if to.write_all(&buf[..n]).is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// The most recent line that looks like an error, e.g. `error: No playable streams found`.
+fn last_error(tail: &VecDeque<String>) -> Option<String> {
+    tail.iter()
+        .rev()
+        .find_map(|line| line.split_once("error:").map(|(_, reason)| reason.trim()))
+        .filter(|reason| !reason.is_empty())
+        .map(str::to_owned)
+}
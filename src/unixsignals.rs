@@ -0,0 +1,103 @@
+use std::io;
+use std::mem;
+use std::process::Child;
+use std::ptr;
+use std::sync::atomic::{AtomicI32, Ordering};
+
+use libc::{c_int, SIGHUP, SIGINT, SIGTERM, SIG_IGN};
+
+use crate::debug::debug;
+use crate::exit::{Code, Failure};
+use crate::streamlink::MAX_RUNNING;
+
+/// The signals passed on to streamlink rather than killing cbc-sl and leaving it running.
+const FORWARDED: [c_int; 3] = [SIGINT, SIGTERM, SIGHUP];
+
+/// The process groups signals are passed on to while they run, by their leader's pid, or 0.
+static CHILDREN: [AtomicI32; MAX_RUNNING] = [const { AtomicI32::new(0) }; MAX_RUNNING];
+/// The last forwarded signal we got, or 0.
+static RECEIVED: AtomicI32 = AtomicI32::new(0);
+
+/// Puts back the signal handlers that were there before [forward_to].
+pub(crate) struct Guard {
+    previous: Vec<(c_int, libc::sigaction)>,
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        for (signal, action) in &self.previous {
+            unsafe { libc::sigaction(*signal, action, ptr::null_mut()) };
+        }
+        for child in &CHILDREN {
+            child.store(0, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Until the guard is dropped, pass SIGINT, SIGTERM and SIGHUP on to the process groups of
+/// `children` instead of dying from them, so they get to shut down (and close the player) before
+/// we do. Each child must lead its own group (see [std::os::unix::process::CommandExt::process_group]),
+/// which also means Ctrl-C in the terminal only reaches us, and is passed on from here. Call
+/// [take_failure] afterwards to exit the way the signal asked. Signals that were being ignored,
+/// as under `nohup`, stay ignored.
+pub(crate) fn forward_to(children: &[&Child]) -> io::Result<Guard> {
+    if children.len() > MAX_RUNNING {
+        return Err(io::Error::other(format!("can't pass signals on to over {MAX_RUNNING}")));
+    }
+    for (slot, child) in CHILDREN.iter().zip(children) {
+        slot.store(child.id() as i32, Ordering::SeqCst);
+    }
+    let mut guard = Guard { previous: Vec::new() };
+    for signal in FORWARDED {
+        unsafe {
+            let mut previous: libc::sigaction = mem::zeroed();
+            if libc::sigaction(signal, ptr::null(), &mut previous) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if previous.sa_sigaction == SIG_IGN {
+                continue;
+            }
+            let mut action: libc::sigaction = mem::zeroed();
+            action.sa_sigaction = on_signal as extern "C" fn(c_int) as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            if libc::sigaction(signal, &action, ptr::null_mut()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            guard.previous.push((signal, previous));
+        }
+    }
+    Ok(guard)
+}
+
+/// Whether a signal meant for streamlink has come in, which [take_failure] will act on.
+pub(crate) fn received() -> bool {
+    RECEIVED.load(Ordering::SeqCst) != 0
+}
+
+/// If a signal was forwarded, the error to exit with now that streamlink is done, so whatever
+/// sent it sees the usual 128 + signal number (e.g. 130 in a shell for SIGINT). Returning it
+/// rather than dying from the signal lets cbc-sl finish up on the way out.
+pub(crate) fn take_failure() -> Option<Failure> {
+    let signal = RECEIVED.swap(0, Ordering::SeqCst);
+    if signal == 0 {
+        return None;
+    }
+    debug!("stopped_by signal={signal}");
+    Some(match signal {
+        SIGHUP => Failure::new(Code::Hangup, "stopped by SIGHUP"),
+        SIGTERM => Failure::new(Code::Terminated, "stopped by SIGTERM"),
+        _ => Failure::new(Code::Interrupted, "interrupted"),
+    })
+}
+
+extern "C" fn on_signal(signal: c_int) {
+    RECEIVED.store(signal, Ordering::SeqCst);
+    for child in &CHILDREN {
+        let pid = child.load(Ordering::SeqCst);
+        if pid > 0 {
+            // the whole group, so the player streamlink started hears about it too
+            unsafe { libc::kill(-pid, signal) };
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use std::os::windows::io::AsRawHandle;
+use std::process::Child;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::Result;
+use once_cell::sync::OnceCell;
+use windows::Win32::{
+    Foundation::{BOOL, FALSE, HANDLE, TRUE},
+    System::{
+        Console::{SetConsoleCtrlHandler, CTRL_BREAK_EVENT, CTRL_C_EVENT},
+        JobObjects::{
+            AssignProcessToJobObject, CreateJobObjectW, JobObjectExtendedLimitInformation,
+            SetInformationJobObject, TerminateJobObject, JOBOBJECT_EXTENDED_LIMIT_INFORMATION,
+            JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+        },
+    },
+};
+
+use crate::debug::debug;
+
+/// A job object that streamlink and everything it starts belongs to, so they can all be killed
+/// together. `Child::kill` only reaches streamlink itself, leaving the player running.
+struct Job(HANDLE);
+
+// The handle is only used with thread-safe kernel calls
+unsafe impl Send for Job {}
+unsafe impl Sync for Job {}
+
+static JOB: OnceCell<Job> = OnceCell::new();
+/// Whether something is in the job right now, so Ctrl-C should go to it rather than to us.
+static RUNNING: AtomicBool = AtomicBool::new(false);
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Clears [RUNNING] when the contained processes are done with.
+pub(crate) struct Guard;
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        RUNNING.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Put `children` in the job, so Ctrl-C ends them and anything they start. The job is also
+/// killed if cbc-sl exits without cleaning up, since its handle closes then.
+pub(crate) fn contain(children: &[&Child]) -> Result<Guard> {
+    let job = JOB.get_or_try_init(create)?;
+    for child in children {
+        let process = HANDLE(child.as_raw_handle());
+        unsafe { AssignProcessToJobObject(job.0, process)? };
+    }
+    RUNNING.store(true, Ordering::Relaxed);
+    Ok(Guard)
+}
+
+/// Whether the user pressed Ctrl-C (or Ctrl-Break) while something was contained.
+pub(crate) fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
+
+fn create() -> Result<Job> {
+    let handle = unsafe { CreateJobObjectW(None, None)? };
+    let mut info = JOBOBJECT_EXTENDED_LIMIT_INFORMATION::default();
+    info.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+    unsafe {
+        SetInformationJobObject(
+            handle,
+            JobObjectExtendedLimitInformation,
+            std::ptr::from_ref(&info).cast(),
+            size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+        )?;
+        SetConsoleCtrlHandler(Some(on_ctrl), TRUE)?;
+    }
+    debug!("job object created");
+    Ok(Job(handle))
+}
+
+/// Kill everything in the job on Ctrl-C and let the code waiting on it report the interruption.
+/// With nothing running, or for other events like closing the console, the default handler
+/// runs and exits.
+unsafe extern "system" fn on_ctrl(ctrl_type: u32) -> BOOL {
+    let Some(job) = JOB.get() else { return FALSE };
+    if !RUNNING.load(Ordering::Relaxed) {
+        return FALSE;
+    }
+    INTERRUPTED.store(true, Ordering::Relaxed);
+    let _ = TerminateJobObject(job.0, 1);
+    if ctrl_type == CTRL_C_EVENT || ctrl_type == CTRL_BREAK_EVENT {
+        TRUE
+    } else {
+        FALSE
+    }
+}